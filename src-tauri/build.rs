@@ -13,5 +13,9 @@ fn main() {
         println!("cargo:rustc-env=APP_ENDPOINT={}", app_endpoint);
     }
     
+    if let Ok(license_public_key) = std::env::var("LICENSE_PUBLIC_KEY") {
+        println!("cargo:rustc-env=LICENSE_PUBLIC_KEY={}", license_public_key);
+    }
+    
     tauri_build::build()
 }
@@ -1,49 +1,44 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
-use std::fs;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 use uuid::Uuid;
 
-fn get_payment_endpoint() -> Result<String, String> {
-    if let Ok(endpoint) = env::var("PAYMENT_ENDPOINT") {
-        return Ok(endpoint);
-    }
-    
-    match option_env!("PAYMENT_ENDPOINT") {
-        Some(endpoint) => Ok(endpoint.to_string()),
-        None => Err("PAYMENT_ENDPOINT environment variable not set. Please ensure it's set during the build process.".to_string())
-    }
-}
+use crate::config;
+use crate::error::{PluelyError, PluelyResult};
+use crate::http;
+use crate::license;
+use crate::secrets;
 
-fn get_api_access_key() -> Result<String, String> {
-     if let Ok(key) = env::var("API_ACCESS_KEY") {
-        return Ok(key);
-    }
-    
-     match option_env!("API_ACCESS_KEY") {
-        Some(key) => Ok(key.to_string()),
-        None => Err("API_ACCESS_KEY environment variable not set. Please ensure it's set during the build process.".to_string())
-    }
-}
+// Hashed with the machine ID so the instance name doesn't reveal it
+const INSTANCE_NAME_CONTEXT: &[u8] = b"pluely-instance-v1";
 
-// Secure storage functions using Tauri's app data directory
-fn get_secure_storage_path(app: &AppHandle) -> Result<PathBuf, String> {
-    let app_data_dir = app.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-    
-    // Create the directory if it doesn't exist
-    fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
-    
-    Ok(app_data_dir.join("secure_storage.json"))
+// Same name on every activation from this machine, so reinstalling doesn't use up another slot
+fn get_instance_name() -> String {
+    match machine_uid::get() {
+        Ok(machine_id) => {
+            let mut hasher = Sha256::new();
+            hasher.update(INSTANCE_NAME_CONTEXT);
+            hasher.update(machine_id.trim().as_bytes());
+            let digest = hasher.finalize();
+            let id: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+            format!("pluely-{}-{}", env::consts::OS, id)
+        }
+        Err(e) => {
+            eprintln!("Failed to read machine ID, using a random instance name: {}", e);
+            Uuid::new_v4().to_string()
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-struct SecureStorage {
-    license_key: Option<String>,
-    instance_id: Option<String>,
-    selected_pluely_model: Option<String>,
+// Maps the legacy storage keys used by the settings screen into the `pluely` namespace
+fn storage_key_name(key: &str) -> PluelyResult<&'static str> {
+    match key {
+        "pluely_license_key" => Ok(secrets::LICENSE_KEY),
+        "pluely_instance_id" => Ok(secrets::INSTANCE_ID),
+        "selected_pluely_model" => Ok(secrets::SELECTED_PLUELY_MODEL),
+        _ => Err(PluelyError::config(format!("Invalid storage key: {}", key))),
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,95 +49,52 @@ pub struct StorageItem {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StorageResult {
-    license_key: Option<String>,
+    // The license key itself never goes back to the webview
+    masked_license_key: Option<String>,
     instance_id: Option<String>,
     selected_pluely_model: Option<String>,
 }
 
 #[tauri::command]
-pub async fn secure_storage_save(app: AppHandle, items: Vec<StorageItem>) -> Result<(), String> {
-    let storage_path = get_secure_storage_path(&app)?;
-    
-    let mut storage = if storage_path.exists() {
-        let content = fs::read_to_string(&storage_path)
-            .map_err(|e| format!("Failed to read storage file: {}", e))?;
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        SecureStorage::default()
-    };
+pub async fn secure_storage_save(app: AppHandle, items: Vec<StorageItem>) -> PluelyResult<()> {
+    let store = secrets::store(&app)?;
     
     for item in items {
-        match item.key.as_str() {
-            "pluely_license_key" => storage.license_key = Some(item.value),
-            "pluely_instance_id" => storage.instance_id = Some(item.value),
-            "selected_pluely_model" => storage.selected_pluely_model = Some(item.value),
-            _ => return Err(format!("Invalid storage key: {}", item.key)),
+        // The license key and instance are only written by a successful activation
+        let name = storage_key_name(&item.key)?;
+        if name != secrets::SELECTED_PLUELY_MODEL {
+            return Err(PluelyError::config(format!("Storage key {} can't be changed", item.key)));
         }
+        store.set(secrets::PLUELY, name, &item.value)?;
     }
     
-    let content = serde_json::to_string(&storage)
-        .map_err(|e| format!("Failed to serialize storage: {}", e))?;
-    
-    fs::write(&storage_path, content)
-        .map_err(|e| format!("Failed to write storage file: {}", e))?;
-    
     Ok(())
 }
 
 #[tauri::command]
-pub async fn secure_storage_get(app: AppHandle) -> Result<StorageResult, String> {
-    let storage_path = get_secure_storage_path(&app)?;
-    
-    if !storage_path.exists() {
-        return Ok(StorageResult {
-            license_key: None,
-            instance_id: None,
-            selected_pluely_model: None,
-        });
-    }
-    
-    let content = fs::read_to_string(&storage_path)
-        .map_err(|e| format!("Failed to read storage file: {}", e))?;
-    
-    let storage: SecureStorage = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse storage file: {}", e))?;
+pub async fn secure_storage_get(app: AppHandle) -> PluelyResult<StorageResult> {
+    let store = secrets::store(&app)?;
     
     Ok(StorageResult {
-        license_key: storage.license_key,
-        instance_id: storage.instance_id,
-        selected_pluely_model: storage.selected_pluely_model,
+        masked_license_key: store.get(secrets::PLUELY, secrets::LICENSE_KEY)?.as_deref().map(mask_license_key),
+        instance_id: store.get(secrets::PLUELY, secrets::INSTANCE_ID)?,
+        selected_pluely_model: store.get(secrets::PLUELY, secrets::SELECTED_PLUELY_MODEL)?,
     })
 }
 
 #[tauri::command]
-pub async fn secure_storage_remove(app: AppHandle, keys: Vec<String>) -> Result<(), String> {
-    let storage_path = get_secure_storage_path(&app)?;
-    
-    if !storage_path.exists() {
-        return Ok(()); // Nothing to remove
-    }
-    
-    let content = fs::read_to_string(&storage_path)
-        .map_err(|e| format!("Failed to read storage file: {}", e))?;
-    
-    let mut storage: SecureStorage = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse storage file: {}", e))?;
+pub async fn secure_storage_remove(app: AppHandle, keys: Vec<String>) -> PluelyResult<()> {
+    let store = secrets::store(&app)?;
     
     for key in keys {
-        match key.as_str() {
-            "pluely_license_key" => storage.license_key = None,
-            "pluely_instance_id" => storage.instance_id = None,
-            "selected_pluely_model" => storage.selected_pluely_model = None,
-            _ => return Err(format!("Invalid storage key: {}", key)),
+        let name = storage_key_name(&key)?;
+        store.remove(secrets::PLUELY, name)?;
+        // The cached validation belongs to the removed license
+        if name == secrets::LICENSE_KEY {
+            store.remove(secrets::PLUELY, secrets::LICENSE_VALIDATION)?;
         }
     }
     
-    let content = serde_json::to_string(&storage)
-        .map_err(|e| format!("Failed to serialize storage: {}", e))?;
-    
-    fs::write(&storage_path, content)
-        .map_err(|e| format!("Failed to write storage file: {}", e))?;
-    
     Ok(())
 }
 
@@ -158,6 +110,38 @@ pub struct ActivationResponse {
     error: Option<String>,
     license_key: Option<String>,
     instance: Option<InstanceInfo>,
+    // Signed verdict for offline validation, stays on the Rust side
+    #[serde(default, skip_serializing)]
+    validation_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeactivationRequest {
+    license_key: String,
+    instance_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeactivationResponse {
+    deactivated: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstancesRequest {
+    license_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstancesResponse {
+    #[serde(default)]
+    instances: Vec<InstanceInfo>,
+    activation_limit: Option<u32>,
+    activation_usage: Option<u32>,
+    error: Option<String>,
+    // Filled in locally so the settings screen can mark this machine
+    #[serde(default)]
+    current_instance_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -175,13 +159,13 @@ pub struct CheckoutResponse {
 }
 
 #[tauri::command]
-pub async fn activate_license_api(license_key: String) -> Result<ActivationResponse, String> {
-    // Get payment endpoint and API access key from environment
-    let payment_endpoint = get_payment_endpoint()?;
-    let api_access_key = get_api_access_key()?;
+pub async fn activate_license_api(app: AppHandle, license_key: String) -> PluelyResult<ActivationResponse> {
+    // Get payment endpoint and API access key from the active endpoint profile
+    let payment_endpoint = config::get_payment_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
     
-    // Generate UUID for instance name
-    let instance_name = Uuid::new_v4().to_string();
+    // Stable per machine, the payment server reuses the instance on reactivation
+    let instance_name = get_instance_name();
     
     // Prepare activation request
     let activation_request = ActivationRequest {
@@ -190,7 +174,7 @@ pub async fn activate_license_api(license_key: String) -> Result<ActivationRespo
     };
     
     // Make HTTP request to activation endpoint with authorization header
-    let client = reqwest::Client::new();
+    let client = http::client(&app);
     let url = format!("{}/activate", payment_endpoint);
     
     let response = client
@@ -200,44 +184,118 @@ pub async fn activate_license_api(license_key: String) -> Result<ActivationRespo
         .json(&activation_request)
         .send()
         .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make chat request: {}", parts[0])
-                } else {
-                    format!("Failed to make chat request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make chat request: {}", error_msg)
-            }
-        })?;
+        .map_err(|e| PluelyError::from(e).context("Failed to make activation request"))?;
     
     let activation_response: ActivationResponse = response
         .json()
         .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make chat request: {}", parts[0])
-                } else {
-                    format!("Failed to make chat request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make chat request: {}", error_msg)
-            }
-        })?;
+        .map_err(|e| PluelyError::from(e).context("Failed to parse activation response"))?;
+
+    if let (true, Some(instance)) = (activation_response.activated, &activation_response.instance) {
+        let store = secrets::store(&app)?;
+        store.set(secrets::PLUELY, secrets::LICENSE_KEY, &license_key)?;
+        store.set(secrets::PLUELY, secrets::INSTANCE_ID, &instance.id)?;
+        license::record_activation(&app, &instance.id, activation_response.validation_token.clone())?;
+    }
     
     Ok(activation_response)
 }
 
+#[tauri::command]
+pub async fn deactivate_license(app: AppHandle, instance_id: Option<String>) -> PluelyResult<DeactivationResponse> {
+    // Get payment endpoint and API access key from the active endpoint profile
+    let payment_endpoint = config::get_payment_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
+
+    let store = secrets::store(&app)?;
+    let license_key = store.get(secrets::PLUELY, secrets::LICENSE_KEY)?
+        .ok_or_else(|| PluelyError::auth("No license found. Please activate your license first."))?;
+    let current_instance_id = store.get(secrets::PLUELY, secrets::INSTANCE_ID)?;
+
+    // Defaults to this machine, another instance can be freed from the instance list
+    let instance_id = instance_id
+        .or_else(|| current_instance_id.clone())
+        .ok_or_else(|| PluelyError::auth("Instance ID not found"))?;
+
+    let deactivation_request = DeactivationRequest {
+        license_key,
+        instance_id: instance_id.clone(),
+    };
+
+    // Make HTTP request to deactivation endpoint with authorization header
+    let client = http::client(&app);
+    let url = format!("{}/deactivate", payment_endpoint);
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_access_key))
+        .json(&deactivation_request)
+        .send()
+        .await
+        .map_err(|e| PluelyError::from(e).context("Failed to make deactivation request"))?;
+
+    if !response.status().is_success() {
+        return Err(PluelyError::from_response(response).await.context("Deactivation failed"));
+    }
+
+    let deactivation_response: DeactivationResponse = response
+        .json()
+        .await
+        .map_err(|e| PluelyError::from(e).context("Failed to parse deactivation response"))?;
+
+    if deactivation_response.deactivated && current_instance_id.as_deref() == Some(instance_id.as_str()) {
+        // This machine no longer holds an activation, forget the local license
+        for name in secrets::LICENSE_SECRETS {
+            store.remove(secrets::PLUELY, name)?;
+        }
+    }
+
+    Ok(deactivation_response)
+}
+
+#[tauri::command]
+pub async fn list_license_instances(app: AppHandle) -> PluelyResult<InstancesResponse> {
+    // Get payment endpoint and API access key from the active endpoint profile
+    let payment_endpoint = config::get_payment_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
+
+    let store = secrets::store(&app)?;
+    let license_key = store.get(secrets::PLUELY, secrets::LICENSE_KEY)?
+        .ok_or_else(|| PluelyError::auth("No license found. Please activate your license first."))?;
+
+    // Make HTTP request to instances endpoint with authorization header
+    let client = http::client(&app);
+    let url = format!("{}/instances", payment_endpoint);
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_access_key))
+        .json(&InstancesRequest { license_key })
+        .send()
+        .await
+        .map_err(|e| PluelyError::from(e).context("Failed to make instances request"))?;
+
+    if !response.status().is_success() {
+        return Err(PluelyError::from_response(response).await.context("Listing instances failed"));
+    }
+
+    let mut instances_response: InstancesResponse = response
+        .json()
+        .await
+        .map_err(|e| PluelyError::from(e).context("Failed to parse instances response"))?;
+    instances_response.current_instance_id = store.get(secrets::PLUELY, secrets::INSTANCE_ID)?;
+
+    Ok(instances_response)
+}
+
 #[tauri::command]
 pub fn mask_license_key_cmd(license_key: String) -> String {
+    mask_license_key(&license_key)
+}
+
+fn mask_license_key(license_key: &str) -> String {
     if license_key.len() <= 8 {
         return "*".repeat(license_key.len());
     }
@@ -250,13 +308,13 @@ pub fn mask_license_key_cmd(license_key: String) -> String {
 }
 
 #[tauri::command]
-pub async fn get_checkout_url() -> Result<CheckoutResponse, String> {
-    // Get payment endpoint and API access key from environment
-    let payment_endpoint = get_payment_endpoint()?;
-    let api_access_key = get_api_access_key()?;
+pub async fn get_checkout_url(app: AppHandle) -> PluelyResult<CheckoutResponse> {
+    // Get payment endpoint and API access key from the active endpoint profile
+    let payment_endpoint = config::get_payment_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
     
     // Make HTTP request to checkout endpoint with authorization header
-    let client = reqwest::Client::new();
+    let client = http::client(&app);
     let url = format!("{}/checkout", payment_endpoint);
     
     let response = client
@@ -266,37 +324,12 @@ pub async fn get_checkout_url() -> Result<CheckoutResponse, String> {
         .json(&serde_json::json!({}))
         .send()
         .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make chat request: {}", parts[0])
-                } else {
-                    format!("Failed to make chat request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make chat request: {}", error_msg)
-            }
-        })?;
+        .map_err(|e| PluelyError::from(e).context("Failed to make checkout request"))?;
     
     let checkout_response: CheckoutResponse = response
         .json()
         .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make chat request: {}", parts[0])
-                } else {
-                    format!("Failed to make chat request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make chat request: {}", error_msg)
-            }
-        })?;    
+        .map_err(|e| PluelyError::from(e).context("Failed to parse checkout response"))?;
+    
     Ok(checkout_response)
 }
\ No newline at end of file
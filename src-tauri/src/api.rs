@@ -1,67 +1,27 @@
 use serde::{Deserialize, Serialize};
-use std::env;
-use tauri::{AppHandle, Manager, Emitter};
-use futures_util::StreamExt;
-use std::fs;
-use std::path::PathBuf;
-
-fn get_app_endpoint() -> Result<String, String> {
-    if let Ok(endpoint) = env::var("APP_ENDPOINT") {
-        return Ok(endpoint);
-    }
-    
-    match option_env!("APP_ENDPOINT") {
-        Some(endpoint) => Ok(endpoint.to_string()),
-        None => Err("APP_ENDPOINT environment variable not set. Please ensure it's set during the build process.".to_string())
-    }
-}
-
-fn get_api_access_key() -> Result<String, String> {
-    if let Ok(key) = env::var("API_ACCESS_KEY") {
-        return Ok(key);
-    }
-    
-    match option_env!("API_ACCESS_KEY") {
-        Some(key) => Ok(key.to_string()),
-        None => Err("API_ACCESS_KEY environment variable not set. Please ensure it's set during the build process.".to_string())
-    }
-}
-
-// Secure storage functions
-fn get_secure_storage_path(app: &AppHandle) -> Result<PathBuf, String> {
-    let app_data_dir = app.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-    
-    fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
-    
-    Ok(app_data_dir.join("secure_storage.json"))
-}
-
-#[derive(Debug, Serialize, Deserialize, Default)]
-struct SecureStorage {
-    license_key: Option<String>,
-    instance_id: Option<String>,
-    selected_pluely_model: Option<String>,
-}
-
-async fn get_stored_credentials(app: &AppHandle) -> Result<(String, String, Option<Model>), String> {
-    let storage_path = get_secure_storage_path(app)?;
-    
-    if !storage_path.exists() {
-        return Err("No license found. Please activate your license first.".to_string());
-    }
-    
-    let content = fs::read_to_string(&storage_path)
-        .map_err(|e| format!("Failed to read storage file: {}", e))?;
-    
-    let storage: SecureStorage = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse storage file: {}", e))?;
-    
-    let license_key = storage.license_key.ok_or("License key not found".to_string())?;
-    let instance_id = storage.instance_id.ok_or("Instance ID not found".to_string())?;
-
-    let selected_model: Option<Model> = storage.selected_pluely_model
+use tauri::AppHandle;
+
+use crate::config;
+use crate::error::{PluelyError, PluelyResult};
+use crate::http;
+use crate::license;
+use crate::local_stt;
+use crate::providers;
+use crate::retry::{self, RetryPolicy};
+use crate::secrets;
+use crate::streams::{spawn_chat_stream, stream_response};
+
+async fn get_stored_credentials(app: &AppHandle) -> PluelyResult<(String, String, Option<Model>)> {
+    let store = secrets::store(app)?;
+    
+    let license_key = store.get(secrets::PLUELY, secrets::LICENSE_KEY)?
+        .ok_or_else(|| PluelyError::auth("No license found. Please activate your license first."))?;
+    let instance_id = store.get(secrets::PLUELY, secrets::INSTANCE_ID)?
+        .ok_or_else(|| PluelyError::auth("Instance ID not found"))?;
+    // Revoked, expired, or offline for longer than the grace period
+    license::ensure_valid(app)?;
+
+    let selected_model: Option<Model> = store.get(secrets::PLUELY, secrets::SELECTED_PLUELY_MODEL)?
         .and_then(|json_str| serde_json::from_str(&json_str).ok());
     
     Ok((license_key, instance_id, selected_model))
@@ -80,6 +40,27 @@ pub struct AudioResponse {
     error: Option<String>,
 }
 
+impl AudioResponse {
+    // The transcription, or the error the backend reported instead
+    pub fn into_transcription(self) -> PluelyResult<String> {
+        match (self.success, self.transcription) {
+            (true, Some(transcription)) => Ok(transcription),
+            _ => Err(PluelyError::audio(self.error.unwrap_or_else(|| "Transcription failed".to_string()))),
+        }
+    }
+}
+
+// Where `transcribe_audio` runs, chosen per request
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SttEngine {
+    // Pluely API `/api/audio`
+    #[default]
+    Remote,
+    // whisper.cpp on this machine, needs the `local-stt` feature
+    Local,
+}
+
 // Chat API Structs
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatRequest {
@@ -120,13 +101,28 @@ pub struct ModelsResponse {
 pub async fn transcribe_audio(
     app: AppHandle,
     audio_base64: String,
-) -> Result<AudioResponse, String> {
-    // Get environment variables
-    let app_endpoint = get_app_endpoint()?;
-    let api_access_key = get_api_access_key()?;
+    engine: Option<SttEngine>,
+) -> PluelyResult<AudioResponse> {
+    transcribe(&app, audio_base64, engine.unwrap_or_default()).await
+}
+
+// Shared with the system audio pipeline, which transcribes segments itself in incremental mode
+pub async fn transcribe(app: &AppHandle, audio_base64: String, engine: SttEngine) -> PluelyResult<AudioResponse> {
+    if let SttEngine::Local = engine {
+        let transcription = local_stt::transcribe(app, audio_base64).await?;
+        return Ok(AudioResponse {
+            success: true,
+            transcription: Some(transcription),
+            error: None,
+        });
+    }
+
+    // Get endpoint and API access key from the active endpoint profile
+    let app_endpoint = config::get_app_endpoint(app)?;
+    let api_access_key = config::get_api_access_key(app)?;
     
     // Get stored credentials
-    let (license_key, instance_id, _) = get_stored_credentials(&app).await?;
+    let (license_key, instance_id, _) = get_stored_credentials(app).await?;
     
     // Prepare audio request
     let audio_request = AudioRequest {
@@ -135,59 +131,31 @@ pub async fn transcribe_audio(
     };
     
     // Make HTTP request to audio endpoint
-    let client = reqwest::Client::new();
+    let client = http::client(app);
     let url = format!("{}/api/audio", app_endpoint);
     
-    let response = client
+    let request = client
         .post(&url)
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_access_key))
         .header("license_key", &license_key)
         .header("instance", &instance_id)
-        .json(&audio_request)
-        .send()
-        .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make audio request: {}", parts[0])
-                } else {
-                    format!("Failed to make audio request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make audio request: {}", error_msg)
-            }
-        })?;
+        .json(&audio_request);
     
-    // Check if the response is successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown server error".to_string());
-        
-        // Try to parse error as JSON to get a more specific error message
-        if let Ok(error_json) = serde_json::from_str::<serde_json::Value>(&error_text) {
-            if let Some(error_msg) = error_json.get("error").and_then(|e| e.as_str()) {
-                return Err(format!("Server error ({}): {}", status, error_msg));
-            } else if let Some(message) = error_json.get("message").and_then(|m| m.as_str()) {
-                return Err(format!("Server error ({}): {}", status, message));
-            }
-        }
-        
-        return Err(format!("Server error ({}): {}", status, error_text));
-    }
+    // Retries transient failures, non-success statuses come back as errors
+    let response = retry::send(request, &http::retry_policy(app))
+        .await
+        .map_err(|e| e.context("Failed to make audio request"))?;
     
     let audio_response: AudioResponse = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse audio response: {}", e))?;
+        .map_err(|e| PluelyError::from(e).context("Failed to parse audio response"))?;
     
     Ok(audio_response)
 }
 
-// Chat API Command with Streaming
+// Chat API Command with Streaming, returns the request ID tagged on every stream event
 #[tauri::command]
 pub async fn chat_stream(
     app: AppHandle,
@@ -195,10 +163,10 @@ pub async fn chat_stream(
     system_prompt: Option<String>,
     image_base64: Option<serde_json::Value>,
     history: Option<String>,
-) -> Result<String, String> {
-    // Get environment variables
-    let app_endpoint = get_app_endpoint()?;
-    let api_access_key = get_api_access_key()?;
+) -> PluelyResult<String> {
+    // Get endpoint and API access key from the active endpoint profile
+    let app_endpoint = config::get_app_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
     
     // Get stored credentials
     let (license_key, instance_id, selected_model) = get_stored_credentials(&app).await?;
@@ -212,11 +180,11 @@ pub async fn chat_stream(
         history
     };
     
-    // Make HTTP request to chat endpoint with streaming
-    let client = reqwest::Client::new();
+    // Build HTTP request to chat endpoint with streaming
+    let client = http::client(&app);
     let url = format!("{}/api/chat?stream=true", app_endpoint);
     
-    let response = client
+    let request = client
         .post(&url)
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_access_key))
@@ -224,160 +192,49 @@ pub async fn chat_stream(
         .header("instance", &instance_id)
         .header("provider", &provider.unwrap_or("None".to_string()))
         .header("model", &model.unwrap_or("None".to_string()))
-        .json(&chat_request)
-        .send()
-        .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make chat request: {}", parts[0])
-                } else {
-                    format!("Failed to make chat request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make chat request: {}", error_msg)
-            }
-        })?;
-    
-    // Check if the response is successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown server error".to_string());
-        
-        // Try to parse error as JSON to get a more specific error message
-        if let Ok(error_json) = serde_json::from_str::<serde_json::Value>(&error_text) {
-            if let Some(error_msg) = error_json.get("error").and_then(|e| e.as_str()) {
-                return Err(format!("Server error ({}): {}", status, error_msg));
-            } else if let Some(message) = error_json.get("message").and_then(|m| m.as_str()) {
-                return Err(format!("Server error ({}): {}", status, message));
-            }
-        }
-        
-        return Err(format!("Server error ({}): {}", status, error_text));
-    }
-    
-    // Handle streaming response
-    let mut stream = response.bytes_stream();
-    let mut full_response = String::new();
-    let mut buffer = String::new();
-    
-    while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(bytes) => {
-                let chunk_str = String::from_utf8_lossy(&bytes);
-                buffer.push_str(&chunk_str);
-                
-                // Process complete lines
-                let lines: Vec<&str> = buffer.split('\n').collect();
-                let incomplete_line = lines.last().unwrap_or(&"").to_string();
-                
-                for line in &lines[..lines.len()-1] { // Process all but the last (potentially incomplete) line
-                    let trimmed_line = line.trim();
-                    
-                    if trimmed_line.starts_with("data: ") {
-                        let json_str = trimmed_line.strip_prefix("data: ").unwrap_or("");
-                        
-                        if json_str == "[DONE]" {
-                            break;
-                        }
-                        
-                        if !json_str.is_empty() {
-                            // Try to parse the JSON and extract content
-                            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(json_str) {
-                                if let Some(choices) = parsed.get("choices").and_then(|c| c.as_array()) {
-                                    if let Some(first_choice) = choices.first() {
-                                        if let Some(delta) = first_choice.get("delta") {
-                                            if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                                full_response.push_str(content);
-                                                // Emit just the content to frontend
-                                                let _ = app.emit("chat_stream_chunk", content);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // Update buffer with incomplete line
-                buffer = incomplete_line;
-            }
-            Err(e) => {
-                return Err(format!("Stream error: {}", e));
-            }
-        }
-    }
-    
-    // Emit completion event
-    let _ = app.emit("chat_stream_complete", &full_response);
-    
-    Ok(full_response)
+        .json(&chat_request);
+
+    // The chat endpoint streams OpenAI-style `chat.completion.chunk` events
+    let policy = http::retry_policy(&app);
+    Ok(spawn_chat_stream(&app, |sink| {
+        stream_response(request, policy, sink, "chat", providers::parse_openai_event)
+    }))
 }
 
 // Models API Command
 #[tauri::command]
-pub async fn fetch_models() -> Result<Vec<Model>, String> {
-    // Get environment variables
-    let app_endpoint = get_app_endpoint()?;
-    let api_access_key = get_api_access_key()?;
+pub async fn fetch_models(app: AppHandle) -> PluelyResult<Vec<Model>> {
+    // Get endpoint and API access key from the active endpoint profile
+    let app_endpoint = config::get_app_endpoint(&app)?;
+    let api_access_key = config::get_api_access_key(&app)?;
     
     // Make HTTP request to models endpoint
-    let client = reqwest::Client::new();
+    let client = http::client(&app);
     let url = format!("{}/api/models", app_endpoint);
     
-    let response = client
+    let request = client
         .post(&url)
         .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_access_key))
-        .send()
+        .header("Authorization", format!("Bearer {}", api_access_key));
+    
+    // Retries transient failures, non-success statuses come back as errors
+    // Only lists the models, so repeating it is harmless
+    let policy = RetryPolicy { idempotent: true, ..http::retry_policy(&app) };
+    let response = retry::send(request, &policy)
         .await
-        .map_err(|e| {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("url (") {
-                // Remove the URL part from the error message
-                let parts: Vec<&str> = error_msg.split(" for url (").collect();
-                if parts.len() > 1 {
-                    format!("Failed to make models request: {}", parts[0])
-                } else {
-                    format!("Failed to make models request: {}", error_msg)
-                }
-            } else {
-                format!("Failed to make models request: {}", error_msg)
-            }
-        })?;
-        
-    // Check if the response is successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown server error".to_string());
-        
-        // Try to parse error as JSON to get a more specific error message
-        if let Ok(error_json) = serde_json::from_str::<serde_json::Value>(&error_text) {
-            if let Some(error_msg) = error_json.get("error").and_then(|e| e.as_str()) {
-                return Err(format!("Server error ({}): {}", status, error_msg));
-            } else if let Some(message) = error_json.get("message").and_then(|m| m.as_str()) {
-                return Err(format!("Server error ({}): {}", status, message));
-            }
-        }
-        
-        return Err(format!("Server error ({}): {}", status, error_text));
-    }
+        .map_err(|e| e.context("Failed to make models request"))?;
     
     let models_response: ModelsResponse = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse models response: {}", e))?;
+        .map_err(|e| PluelyError::from(e).context("Failed to parse models response"))?;
         
     Ok(models_response.models)
 }
 
-// Helper command to check if license is available
+// Helper command to check if a usable license is available
 #[tauri::command]
-pub async fn check_license_status(app: AppHandle) -> Result<bool, String> {
+pub async fn check_license_status(app: AppHandle) -> PluelyResult<bool> {
     match get_stored_credentials(&app).await {
         Ok(_) => Ok(true),
         Err(_) => Ok(false),
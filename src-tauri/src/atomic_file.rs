@@ -0,0 +1,106 @@
+// Pluely crash-safe file writes, used for storage files that must never be left truncated
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{PluelyError, PluelyResult};
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Writes to a temp file in the same directory, syncs it, then renames it over `path`.
+/// The previous contents are kept as `<path>.bak` for `read_with_recovery`.
+pub fn write(path: &Path, contents: &[u8]) -> PluelyResult<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Secrets live in these files, keep them private to the user
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(&tmp_path)
+        .map_err(|e| PluelyError::storage(format!("Failed to create temp file: {}", e)))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| PluelyError::storage(format!("Failed to write temp file: {}", e)))?;
+    drop(file);
+
+    if path.exists() {
+        if let Err(e) = fs::copy(path, backup_path(path)) {
+            eprintln!("Failed to back up {}: {}", path.display(), e);
+        }
+    }
+
+    fs::rename(&tmp_path, path)
+        .map_err(|e| PluelyError::storage(format!("Failed to replace file: {}", e)))?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+
+    Ok(())
+}
+
+/// Replaces the backup with the current contents, e.g. once removed data must not linger in it.
+pub fn refresh_backup(path: &Path) -> PluelyResult<()> {
+    fs::copy(path, backup_path(path))
+        .map_err(|e| PluelyError::storage(format!("Failed to refresh backup of {}: {}", path.display(), e)))?;
+    Ok(())
+}
+
+/// Reads and decodes `path`, falling back to the backup if the file is corrupted.
+/// A corrupted file is moved aside to `<path>.corrupt` rather than deleted. Returns
+/// `None` when there is nothing usable to read.
+pub fn read_with_recovery<T>(
+    path: &Path,
+    decode: impl Fn(&[u8]) -> PluelyResult<T>,
+) -> PluelyResult<Option<T>> {
+    let backup = backup_path(path);
+
+    if path.exists() {
+        let contents = fs::read(path)
+            .map_err(|e| PluelyError::storage(format!("Failed to read storage file: {}", e)))?;
+        match decode(&contents) {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) => {
+                eprintln!("{} is corrupted, trying backup: {}", path.display(), e);
+                let _ = fs::rename(path, with_suffix(path, ".corrupt"));
+            }
+        }
+    }
+
+    if !backup.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read(&backup)
+        .map_err(|e| PluelyError::storage(format!("Failed to read backup file: {}", e)))?;
+    match decode(&contents) {
+        Ok(value) => {
+            // Restore the backup so the next read doesn't need recovery
+            if let Err(e) = write(path, &contents) {
+                eprintln!("Failed to restore {} from backup: {}", path.display(), e);
+            }
+            tracing::warn!(path = %path.display(), "restored_storage_from_backup");
+            Ok(Some(value))
+        }
+        Err(e) => {
+            eprintln!("Backup {} is corrupted too: {}", backup.display(), e);
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,351 @@
+// Pluely backend endpoints, read from pluely.toml in the app config dir so self-hosted
+// deployments don't need a custom build
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::RwLock;
+use tauri::{AppHandle, Manager};
+
+use crate::atomic_file;
+use crate::error::{PluelyError, PluelyResult};
+use crate::secrets;
+use crate::settings_file;
+
+const CONFIG_FILE: &str = "pluely.toml";
+// Always available, falls back to the endpoints baked in at build time
+const DEFAULT_PROFILE: &str = "default";
+const MAX_PROFILE_NAME_LEN: usize = 64;
+
+// One backend deployment, unset fields fall back to the env vars
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointProfile {
+    pub app_endpoint: Option<String>,
+    pub payment_endpoint: Option<String>,
+    // The key itself is in the secret store under the profile name
+    pub has_api_access_key: bool,
+    // Plaintext key written by hand in older files, moved into the secret store on load
+    #[serde(skip_serializing)]
+    pub api_access_key: Option<String>,
+}
+
+// Contents of pluely.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluelyConfig {
+    pub active_profile: String,
+    pub profiles: BTreeMap<String, EndpointProfile>,
+}
+
+impl Default for PluelyConfig {
+    fn default() -> Self {
+        Self {
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+// Profile as shown in the settings screen, never includes the access key
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointProfileInfo {
+    name: String,
+    app_endpoint: Option<String>,
+    payment_endpoint: Option<String>,
+    has_api_access_key: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointProfiles {
+    active_profile: String,
+    profiles: Vec<EndpointProfileInfo>,
+}
+
+impl PluelyConfig {
+    pub fn validate(&self) -> PluelyResult<()> {
+        for (name, profile) in &self.profiles {
+            validate_profile_name(name)?;
+            for (field, value) in [
+                ("app_endpoint", &profile.app_endpoint),
+                ("payment_endpoint", &profile.payment_endpoint),
+            ] {
+                if let Some(value) = value {
+                    validate_endpoint(value).map_err(|e| e.context(&format!("Profile {}: {}", name, field)))?;
+                }
+            }
+        }
+
+        if !self.has_profile(&self.active_profile) {
+            return Err(PluelyError::config(format!("Unknown endpoint profile: {}", self.active_profile)));
+        }
+        Ok(())
+    }
+
+    fn has_profile(&self, name: &str) -> bool {
+        name == DEFAULT_PROFILE || self.profiles.contains_key(name)
+    }
+
+    fn active(&self) -> EndpointProfile {
+        self.profiles.get(&self.active_profile).cloned().unwrap_or_default()
+    }
+
+    fn profiles(&self) -> EndpointProfiles {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        let default_profile = DEFAULT_PROFILE.to_string();
+        if !self.profiles.contains_key(DEFAULT_PROFILE) {
+            names.insert(0, &default_profile);
+        }
+
+        EndpointProfiles {
+            active_profile: self.active_profile.clone(),
+            profiles: names
+                .into_iter()
+                .map(|name| {
+                    let profile = self.profiles.get(name).cloned().unwrap_or_default();
+                    EndpointProfileInfo {
+                        name: name.clone(),
+                        app_endpoint: profile.app_endpoint,
+                        payment_endpoint: profile.payment_endpoint,
+                        has_api_access_key: profile.has_api_access_key || profile.api_access_key.is_some(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+fn validate_profile_name(name: &str) -> PluelyResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_PROFILE_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(PluelyError::config(format!("Invalid endpoint profile name: {}", name)))
+    }
+}
+
+fn validate_endpoint(value: &str) -> PluelyResult<()> {
+    let url = reqwest::Url::parse(value)
+        .map_err(|e| PluelyError::config(format!("Invalid endpoint URL {}: {}", value, e)))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(PluelyError::config(format!("Endpoint must be http or https: {}", value)));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(PluelyError::config(format!("Endpoint can't have a query or fragment: {}", value)));
+    }
+    Ok(())
+}
+
+// Managed config, reloaded from disk whenever a profile is switched
+pub struct Config(RwLock<PluelyConfig>);
+
+fn read_config(app: &AppHandle) -> PluelyResult<PluelyConfig> {
+    let path = settings_file::path(app, CONFIG_FILE)?;
+    if !path.exists() {
+        return Ok(PluelyConfig::default());
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| PluelyError::storage(format!("Failed to read {}: {}", CONFIG_FILE, e)))?;
+    let config: PluelyConfig = toml::from_str(&content)
+        .map_err(|e| PluelyError::config(format!("Failed to parse {}: {}", CONFIG_FILE, e)))?;
+    config.validate()?;
+
+    Ok(config)
+}
+
+fn write_config(app: &AppHandle, config: &PluelyConfig) -> PluelyResult<()> {
+    let content = toml::to_string_pretty(config)
+        .map_err(|e| PluelyError::storage(format!("Failed to serialize {}: {}", CONFIG_FILE, e)))?;
+    atomic_file::write(&settings_file::path(app, CONFIG_FILE)?, content.as_bytes())
+}
+
+// Moves plaintext access keys into the secret store, true when the file must be rewritten without them
+fn migrate_access_keys(app: &AppHandle, config: &mut PluelyConfig) -> PluelyResult<bool> {
+    if config.profiles.values().all(|profile| profile.api_access_key.is_none()) {
+        return Ok(false);
+    }
+
+    let store = secrets::store(app)?;
+    for (name, profile) in config.profiles.iter_mut() {
+        if let Some(key) = profile.api_access_key.take() {
+            store.set(secrets::ENDPOINT, name, &key)?;
+            profile.has_api_access_key = true;
+        }
+    }
+    tracing::info!("migrated_endpoint_access_keys");
+
+    Ok(true)
+}
+
+/// Reads the config at startup. An invalid file falls back to the built-in
+/// endpoints so the app still starts, the error is logged.
+pub fn load(app: &AppHandle) -> Config {
+    let mut config = read_config(app).unwrap_or_else(|e| {
+        eprintln!("Invalid {}, using built-in endpoints: {}", CONFIG_FILE, e);
+        PluelyConfig::default()
+    });
+    // A failed migration keeps the plaintext keys usable until the next start
+    let migrated = migrate_access_keys(app, &mut config)
+        .and_then(|moved| if moved { write_config(app, &config) } else { Ok(()) });
+    if let Err(e) = migrated {
+        eprintln!("Failed to move endpoint access keys into secure storage: {}", e);
+    }
+    Config(RwLock::new(config))
+}
+
+// Env var override, then the active profile, then the value baked in at build time.
+// Baked values only apply to the default profile so a self-hosted profile never
+// receives the hosted access key.
+fn resolve(
+    app: &AppHandle,
+    var: &str,
+    field: impl Fn(&str, EndpointProfile) -> PluelyResult<Option<String>>,
+    baked: Option<&'static str>,
+) -> PluelyResult<String> {
+    if let Ok(value) = env::var(var) {
+        return Ok(value);
+    }
+
+    let config = app.state::<Config>().0.read().unwrap().clone();
+    let value = field(&config.active_profile, config.active())?.or_else(|| {
+        baked
+            .filter(|_| config.active_profile == DEFAULT_PROFILE)
+            .map(str::to_string)
+    });
+
+    match value {
+        Some(value) => Ok(value),
+        None => Err(PluelyError::config(format!(
+            "{} is not set for endpoint profile {}. Set it in {} or the environment.",
+            var, config.active_profile, CONFIG_FILE
+        ))),
+    }
+}
+
+// Env vars skip the file validation, so every endpoint is checked here. Paths are appended with a leading slash.
+fn resolve_endpoint(
+    app: &AppHandle,
+    var: &str,
+    field: impl Fn(EndpointProfile) -> Option<String>,
+    baked: Option<&'static str>,
+) -> PluelyResult<String> {
+    let endpoint = resolve(app, var, |_, profile| Ok(field(profile)), baked)?;
+    validate_endpoint(&endpoint).map_err(|e| e.context(var))?;
+    Ok(endpoint.trim_end_matches('/').to_string())
+}
+
+pub fn get_app_endpoint(app: &AppHandle) -> PluelyResult<String> {
+    resolve_endpoint(app, "APP_ENDPOINT", |p| p.app_endpoint, option_env!("APP_ENDPOINT"))
+}
+
+pub fn get_payment_endpoint(app: &AppHandle) -> PluelyResult<String> {
+    resolve_endpoint(app, "PAYMENT_ENDPOINT", |p| p.payment_endpoint, option_env!("PAYMENT_ENDPOINT"))
+}
+
+pub fn get_api_access_key(app: &AppHandle) -> PluelyResult<String> {
+    resolve(
+        app,
+        "API_ACCESS_KEY",
+        |name, profile| {
+            if profile.has_api_access_key {
+                secrets::store(app)?.get(secrets::ENDPOINT, name)
+            } else {
+                Ok(profile.api_access_key)
+            }
+        },
+        option_env!("API_ACCESS_KEY"),
+    )
+}
+
+#[tauri::command]
+pub fn get_endpoint_profiles(app: AppHandle) -> EndpointProfiles {
+    app.state::<Config>().0.read().unwrap().profiles()
+}
+
+// Parks the active license under the profile being left and brings back the one stored for `to`
+fn swap_license(store: &secrets::Secrets, from: &str, to: &str) -> PluelyResult<()> {
+    for secret in secrets::LICENSE_SECRETS {
+        let parked = format!("{}.{}", from, secret);
+        match store.get(secrets::PLUELY, secret)? {
+            Some(value) => store.set(secrets::PROFILE_LICENSE, &parked, &value)?,
+            None => store.remove(secrets::PROFILE_LICENSE, &parked)?,
+        }
+
+        let restored = format!("{}.{}", to, secret);
+        match store.get(secrets::PROFILE_LICENSE, &restored)? {
+            Some(value) => {
+                store.set(secrets::PLUELY, secret, &value)?;
+                store.remove(secrets::PROFILE_LICENSE, &restored)?;
+            }
+            None => store.remove(secrets::PLUELY, secret)?,
+        }
+    }
+    Ok(())
+}
+
+/// Makes `name` the active endpoint profile. Each profile keeps its own license,
+/// switching back restores the one activated against that profile.
+#[tauri::command]
+pub fn switch_endpoint_profile(app: AppHandle, name: String) -> PluelyResult<EndpointProfiles> {
+    // Re-read the file so hand edits made while the app was running are picked up
+    let mut config = read_config(&app)?;
+    if !config.has_profile(&name) {
+        return Err(PluelyError::config(format!("Unknown endpoint profile: {}", name)));
+    }
+
+    if name != config.active_profile {
+        // A license activated against one backend means nothing to another
+        if let Some(store) = app.try_state::<secrets::Secrets>() {
+            swap_license(&store, &config.active_profile, &name)?;
+        }
+    }
+    config.active_profile = name;
+    // Written below either way, which drops any plaintext keys added by hand
+    migrate_access_keys(&app, &mut config)?;
+    write_config(&app, &config)?;
+
+    let profiles = config.profiles();
+    *app.state::<Config>().0.write().unwrap() = config;
+    tracing::info!(profile = %profiles.active_profile, "switched_endpoint_profile");
+
+    Ok(profiles)
+}
+
+/// Stores or, with `None`, removes the access key of a profile.
+#[tauri::command]
+pub fn set_endpoint_access_key(
+    app: AppHandle,
+    name: String,
+    access_key: Option<String>,
+) -> PluelyResult<EndpointProfiles> {
+    let mut config = read_config(&app)?;
+    if !config.has_profile(&name) {
+        return Err(PluelyError::config(format!("Unknown endpoint profile: {}", name)));
+    }
+    migrate_access_keys(&app, &mut config)?;
+
+    let store = secrets::store(&app)?;
+    let profile = config.profiles.entry(name.clone()).or_default();
+    match access_key.filter(|key| !key.trim().is_empty()) {
+        Some(key) => {
+            store.set(secrets::ENDPOINT, &name, key.trim())?;
+            profile.has_api_access_key = true;
+        }
+        None => {
+            store.remove(secrets::ENDPOINT, &name)?;
+            profile.has_api_access_key = false;
+        }
+    }
+    write_config(&app, &config)?;
+
+    let profiles = config.profiles();
+    *app.state::<Config>().0.write().unwrap() = config;
+    tracing::info!(profile = %name, "updated_endpoint_access_key");
+
+    Ok(profiles)
+}
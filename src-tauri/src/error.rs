@@ -0,0 +1,245 @@
+// Pluely error type returned by Tauri commands, serialized with a `kind` tag so the frontend can match on it
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PluelyError {
+    Network { message: String },
+    Timeout { message: String },
+    Auth { message: String },
+    RateLimited { message: String, retry_after: Option<u64> },
+    Server { status: u16, message: String },
+    Parse { message: String },
+    Storage { message: String },
+    Config { message: String },
+    Unsupported { message: String },
+    // Audio capture, decoding or local transcription
+    Audio { message: String },
+}
+
+pub type PluelyResult<T> = Result<T, PluelyError>;
+
+impl PluelyError {
+    pub fn network(message: impl Into<String>) -> Self {
+        Self::Network { message: message.into() }
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self::Timeout { message: message.into() }
+    }
+
+    pub fn auth(message: impl Into<String>) -> Self {
+        Self::Auth { message: message.into() }
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        Self::Parse { message: message.into() }
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        Self::Storage { message: message.into() }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config { message: message.into() }
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        Self::Unsupported { message: message.into() }
+    }
+
+    pub fn audio(message: impl Into<String>) -> Self {
+        Self::Audio { message: message.into() }
+    }
+
+    // Maps an HTTP error status to the matching variant
+    pub fn from_status(status: u16, message: impl Into<String>, retry_after: Option<u64>) -> Self {
+        let message = message.into();
+        match status {
+            401 | 403 => Self::Auth { message },
+            429 => Self::RateLimited { message, retry_after },
+            _ => Self::Server { status, message },
+        }
+    }
+
+    /// Builds an error from a non-success response, preferring the `error`/`message`
+    /// field of a JSON body over the raw text.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = retry_after_secs(response.headers());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown server error".to_string());
+
+        Self::from_status(status, redact(&error_message_from_body(&error_text)), retry_after)
+    }
+
+    /// Builds an error from one reported inside a stream. It arrives after a 200, so the
+    /// provider's error type stands in for the status, e.g. Anthropic `overloaded_error`,
+    /// OpenAI `rate_limit_exceeded` or Gemini `RESOURCE_EXHAUSTED`.
+    pub fn from_stream_error(body: &str) -> Self {
+        let message = redact(&error_message_from_body(body));
+        let json = serde_json::from_str::<serde_json::Value>(body).unwrap_or_default();
+        let error = json.get("error").filter(|e| e.is_object()).unwrap_or(&json);
+
+        // Gemini also reports the HTTP status it would have used
+        if let Some(status) = error
+            .get("code")
+            .and_then(|c| c.as_u64())
+            .and_then(|c| u16::try_from(c).ok())
+            .filter(|c| (400..600).contains(c))
+        {
+            return Self::from_status(status, message, None).context("Stream error");
+        }
+
+        let kinds: Vec<String> = ["type", "code", "status"]
+            .iter()
+            .filter_map(|field| error.get(*field).and_then(|v| v.as_str()))
+            .map(str::to_lowercase)
+            .collect();
+        let is = |names: &[&str]| kinds.iter().any(|kind| names.contains(&kind.as_str()));
+
+        let error = if kinds.iter().any(|kind| kind.contains("rate_limit")) || is(&["resource_exhausted"]) {
+            Self::RateLimited { message, retry_after: None }
+        } else if is(&["authentication_error", "permission_error", "invalid_api_key", "unauthenticated", "permission_denied"]) {
+            Self::Auth { message }
+        } else if is(&["overloaded_error"]) {
+            Self::Server { status: 529, message }
+        } else if is(&["unavailable"]) {
+            Self::Server { status: 503, message }
+        } else {
+            Self::Server { status: 500, message }
+        };
+        error.context("Stream error")
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Network { message }
+            | Self::Timeout { message }
+            | Self::Auth { message }
+            | Self::RateLimited { message, .. }
+            | Self::Server { message, .. }
+            | Self::Parse { message }
+            | Self::Storage { message }
+            | Self::Config { message }
+            | Self::Unsupported { message }
+            | Self::Audio { message } => message,
+        }
+    }
+
+    // Prefixes the message with what we were doing, e.g. "Failed to make chat request"
+    pub fn context(mut self, context: &str) -> Self {
+        match &mut self {
+            Self::Network { message }
+            | Self::Timeout { message }
+            | Self::Auth { message }
+            | Self::RateLimited { message, .. }
+            | Self::Server { message, .. }
+            | Self::Parse { message }
+            | Self::Storage { message }
+            | Self::Config { message }
+            | Self::Unsupported { message }
+            | Self::Audio { message } => *message = format!("{}: {}", context, message),
+        }
+        self
+    }
+}
+
+impl fmt::Display for PluelyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server { status, message } => write!(f, "Server error ({}): {}", status, message),
+            Self::RateLimited { message, retry_after: Some(secs) } => {
+                write!(f, "Rate limited, retry in {}s: {}", secs, message)
+            }
+            Self::RateLimited { message, retry_after: None } => write!(f, "Rate limited: {}", message),
+            _ => f.write_str(self.message()),
+        }
+    }
+}
+
+impl std::error::Error for PluelyError {}
+
+impl From<reqwest::Error> for PluelyError {
+    fn from(e: reqwest::Error) -> Self {
+        let status = e.status();
+        let is_timeout = e.is_timeout();
+        let is_decode = e.is_decode();
+        let message = redact(&e.without_url().to_string());
+
+        if is_timeout {
+            Self::Timeout { message }
+        } else if is_decode {
+            Self::Parse { message }
+        } else if let Some(status) = status {
+            Self::from_status(status.as_u16(), message, None)
+        } else {
+            Self::Network { message }
+        }
+    }
+}
+
+impl From<serde_json::Error> for PluelyError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse { message: e.to_string() }
+    }
+}
+
+// Parses a `Retry-After` header, given either in seconds or as an HTTP date
+pub fn retry_after_secs(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+
+    // The HTTP-date form, a date already passed means retry now
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .map(|wait| wait.as_secs_f64().ceil() as u64)
+            .unwrap_or(0),
+    )
+}
+
+// Best-effort message from an error body, which may or may not be JSON
+pub fn error_message_from_body(body: &str) -> String {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        if let Some(message) = json
+            .pointer("/error/message")
+            .or_else(|| json.get("error"))
+            .or_else(|| json.get("message"))
+            .and_then(|m| m.as_str())
+        {
+            return message.to_string();
+        }
+    }
+    body.to_string()
+}
+
+/// Removes URLs and anything that looks like a credential from a message
+/// before it is shown in the UI or written to logs.
+pub fn redact(message: &str) -> String {
+    let mut after_bearer = false;
+    message
+        .split(' ')
+        .map(|word| {
+            let bare = word.trim_start_matches(['(', '"', '\'', '<']);
+            let sensitive = after_bearer
+                || bare.starts_with("http://")
+                || bare.starts_with("https://")
+                || bare.starts_with("sk-")
+                || bare.contains("key=")
+                || bare.contains("token=");
+            after_bearer = bare.eq_ignore_ascii_case("bearer");
+            if sensitive {
+                "[redacted]"
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,156 @@
+// Pluely shared HTTP client, built from the network settings and reused by every request
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::error::{PluelyError, PluelyResult};
+use crate::retry::{self, RetryPolicy};
+use crate::settings_file;
+
+const SETTINGS_FILE: &str = "http_settings.json";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+// Idle time between reads, not a total timeout, so long chat streams are fine
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HttpSettings {
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    // http(s):// or socks5(h):// proxy, None falls back to the HTTP(S)_PROXY env vars
+    pub proxy_url: Option<String>,
+    // PEM files with extra root certificates, e.g. a corporate CA bundle
+    pub ca_certificate_paths: Vec<String>,
+    pub user_agent: Option<String>,
+    // Retries of a failed request, 0 disables retrying
+    pub max_retries: u32,
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            proxy_url: None,
+            ca_certificate_paths: Vec::new(),
+            user_agent: None,
+            max_retries: retry::DEFAULT_MAX_RETRIES,
+            retry_base_delay_ms: retry::DEFAULT_BASE_DELAY.as_millis() as u64,
+            retry_max_delay_ms: retry::DEFAULT_MAX_DELAY.as_millis() as u64,
+        }
+    }
+}
+
+impl HttpSettings {
+    // Not idempotent, callers without side effects opt in
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: Duration::from_millis(self.retry_base_delay_ms),
+            max_delay: Duration::from_millis(self.retry_max_delay_ms.max(self.retry_base_delay_ms)),
+            idempotent: false,
+        }
+    }
+
+    pub fn build_client(&self) -> PluelyResult<reqwest::Client> {
+        let user_agent = self
+            .user_agent
+            .clone()
+            .filter(|ua| !ua.trim().is_empty())
+            .unwrap_or_else(|| format!("Pluely/{}", env!("CARGO_PKG_VERSION")));
+
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs.max(1)))
+            .read_timeout(Duration::from_secs(self.read_timeout_secs.max(1)))
+            .user_agent(user_agent);
+
+        if let Some(proxy_url) = self.proxy_url.as_deref().filter(|url| !url.trim().is_empty()) {
+            let proxy = reqwest::Proxy::all(proxy_url.trim())
+                .map_err(|e| PluelyError::config(format!("Invalid proxy URL: {}", e.without_url())))?;
+            builder = builder.proxy(proxy);
+        }
+
+        for path in &self.ca_certificate_paths {
+            let pem = fs::read(path)
+                .map_err(|e| PluelyError::config(format!("Failed to read certificate {}: {}", path, e)))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| PluelyError::config(format!("Invalid certificate {}: {}", path, e)))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        builder
+            .build()
+            .map_err(|e| PluelyError::config(format!("Failed to build HTTP client: {}", e)))
+    }
+}
+
+// Managed HTTP client, rebuilt whenever the settings change
+pub struct HttpClient {
+    client: RwLock<reqwest::Client>,
+    settings: RwLock<HttpSettings>,
+}
+
+impl HttpClient {
+    pub fn new(settings: HttpSettings) -> PluelyResult<Self> {
+        let client = settings.build_client()?;
+        Ok(Self {
+            client: RwLock::new(client),
+            settings: RwLock::new(settings),
+        })
+    }
+
+    // reqwest clients are reference counted, cloning shares the connection pool
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn settings(&self) -> HttpSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    fn apply(&self, settings: HttpSettings) -> PluelyResult<()> {
+        let client = settings.build_client()?;
+        *self.client.write().unwrap() = client;
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+}
+
+// Shorthand for commands
+pub fn client(app: &AppHandle) -> reqwest::Client {
+    app.state::<HttpClient>().client()
+}
+
+pub fn retry_policy(app: &AppHandle) -> RetryPolicy {
+    app.state::<HttpClient>().settings().retry_policy()
+}
+
+/// Builds the client from the saved settings at startup. Broken settings fall back
+/// to the defaults so a bad proxy can still be fixed from the UI.
+pub fn load(app: &AppHandle) -> HttpClient {
+    let settings: HttpSettings = settings_file::load_or_default(app, SETTINGS_FILE, |_| Ok(()));
+
+    HttpClient::new(settings).unwrap_or_else(|e| {
+        eprintln!("Failed to apply HTTP settings, using defaults: {}", e);
+        HttpClient::new(HttpSettings::default()).expect("default HTTP client")
+    })
+}
+
+#[tauri::command]
+pub fn get_http_settings(app: AppHandle) -> HttpSettings {
+    app.state::<HttpClient>().settings()
+}
+
+#[tauri::command]
+pub fn update_http_settings(app: AppHandle, settings: HttpSettings) -> PluelyResult<()> {
+    // Validate by building the client before anything is persisted
+    app.state::<HttpClient>().apply(settings.clone())?;
+
+    settings_file::save(&app, SETTINGS_FILE, &settings)
+}
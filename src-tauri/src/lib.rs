@@ -3,6 +3,18 @@ mod window;
 mod shortcuts;
 mod activate;
 mod api;
+mod atomic_file;
+mod config;
+mod error;
+mod http;
+mod license;
+mod local_stt;
+mod providers;
+mod retry;
+mod secrets;
+mod settings_file;
+mod sse;
+mod streams;
 
 #[cfg(target_os = "macos")]
 use tauri_plugin_macos_permissions;
@@ -13,6 +25,7 @@ use image::{ColorType, ImageEncoder};
 use tauri_plugin_http;
 
 use std::sync::{Arc, Mutex};
+use tauri::Manager;
 use tokio::task::JoinHandle;
 
 mod speaker;
@@ -20,6 +33,7 @@ mod speaker;
 #[derive(Default)]
 pub struct AudioState {
     stream_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    mic_task: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 #[tauri::command]
@@ -71,12 +85,14 @@ fn capture_to_base64() -> Result<String, String> {
 pub fn run() {
     let mut builder = tauri::Builder::default()
         .manage(AudioState::default())
+        .manage(speaker::SelectedCaptureTarget::default())
+        .manage(speaker::CaptureStatsState::default())
+        .manage(streams::ChatStreams::default())
         .manage(shortcuts::WindowVisibility(Mutex::new(false)))
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
-        .plugin(tauri_plugin_keychain::init())
         .plugin(tauri_plugin_shell::init())  // Add shell plugin
         .invoke_handler(tauri::generate_handler![
             greet, 
@@ -89,21 +105,66 @@ pub fn run() {
             shortcuts::set_app_icon_visibility,
             shortcuts::set_always_on_top,
             activate::activate_license_api,
+            activate::deactivate_license,
+            activate::list_license_instances,
             activate::mask_license_key_cmd,
             activate::get_checkout_url,
             activate::secure_storage_save,
             activate::secure_storage_get,
             activate::secure_storage_remove,
+            secrets::secret_set,
+            secrets::secret_remove,
+            secrets::secret_exists,
+            secrets::secret_list,
+            secrets::secret_rotate,
             api::transcribe_audio,
             api::chat_stream,
+            streams::cancel_chat_stream,
             api::fetch_models,
             api::check_license_status,
+            license::validate_license,
+            providers::provider_chat_stream,
+            http::get_http_settings,
+            config::get_endpoint_profiles,
+            config::switch_endpoint_profile,
+            config::set_endpoint_access_key,
+            http::update_http_settings,
             speaker::start_system_audio_capture,
             speaker::stop_system_audio_capture,
+            speaker::start_microphone_capture,
+            speaker::stop_microphone_capture,
+            speaker::start_conversation_capture,
+            speaker::stop_conversation_capture,
+            speaker::get_input_devices,
+            speaker::get_capture_targets,
+            speaker::set_capture_target,
+            speaker::get_capture_stats,
+            speaker::get_vad_config,
+            speaker::update_vad_config,
             speaker::check_system_audio_access,
             speaker::request_system_audio_access
         ])
         .setup(|app| {
+            // Speech segmentation settings, watched by a running capture
+            app.manage(speaker::load_vad_settings(app.handle()));
+
+            // Secret store for license, provider and endpoint credentials
+            match secrets::open(app.handle()) {
+                Ok(store) => {
+                    app.manage(store);
+                }
+                Err(e) => eprintln!("Failed to open secure storage: {}", e),
+            }
+
+            // Backend endpoint profiles from pluely.toml, opened after the secret store holding their access keys
+            app.manage(config::load(app.handle()));
+
+            // Shared HTTP client built from the saved network settings
+            app.manage(http::load(app.handle()));
+
+            // Re-check the license with the payment server in the background
+            license::spawn_periodic_validation(app.handle().clone());
+
             // Setup main window positioning
             window::setup_main_window(app).expect("Failed to setup main window");
             
@@ -0,0 +1,522 @@
+// Pluely license validation, caches the payment server's verdict so the app keeps working offline
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+use crate::config;
+use crate::error::{PluelyError, PluelyResult};
+use crate::http;
+use crate::retry::{self, RetryPolicy};
+use crate::secrets;
+
+// Re-check with the payment server at most once a day
+const VALIDATION_INTERVAL_SECS: u64 = 24 * 60 * 60;
+// How long the last successful validation keeps the app working without network
+const OFFLINE_GRACE_PERIOD_SECS: u64 = 7 * 24 * 60 * 60;
+// How often the background task wakes up to see whether a re-check is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseStatus {
+    Active,
+    Expired,
+    Revoked,
+    // No verdict that can be trusted yet, usable again after the next online check
+    Unvalidated,
+    // Anything else the server reports, e.g. disabled or refunded
+    #[serde(other)]
+    Inactive,
+}
+
+// Last verdict from the payment server, kept in the secret store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LicenseRecord {
+    instance_id: String,
+    status: LicenseStatus,
+    validated_at: u64,
+    expires_at: Option<u64>,
+    // Signed by the payment server, its claims win over the fields above when it verifies
+    token: Option<String>,
+    // Created for a license activated before validation existed, trusted until the first online check
+    #[serde(default)]
+    seeded: bool,
+}
+
+// Payload of a validation token: `base64url(claims).base64url(ed25519 signature)`
+#[derive(Debug, Deserialize)]
+struct TokenClaims {
+    instance_id: String,
+    status: LicenseStatus,
+    issued_at: u64,
+    expires_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidationRequest<'a> {
+    license_key: &'a str,
+    instance_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidationResponse {
+    valid: bool,
+    status: Option<LicenseStatus>,
+    expires_at: Option<u64>,
+    validation_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LicenseState {
+    pub valid: bool,
+    pub status: Option<LicenseStatus>,
+    // The payment server couldn't be reached and the cached verdict was used
+    pub offline: bool,
+    pub validated_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    // Time left before the app has to reach the payment server again
+    pub grace_remaining_secs: Option<u64>,
+}
+
+impl LicenseState {
+    fn unlicensed() -> Self {
+        Self {
+            valid: false,
+            status: None,
+            offline: false,
+            validated_at: None,
+            expires_at: None,
+            grace_remaining_secs: None,
+        }
+    }
+
+    // Activated but never validated, or the stored verdict failed verification
+    fn unvalidated(offline: bool) -> Self {
+        Self {
+            status: Some(LicenseStatus::Unvalidated),
+            offline,
+            ..Self::unlicensed()
+        }
+    }
+}
+
+impl LicenseRecord {
+    fn from_response(instance_id: String, response: ValidationResponse, now: u64) -> Self {
+        let status = if response.valid {
+            LicenseStatus::Active
+        } else {
+            response
+                .status
+                .filter(|status| *status != LicenseStatus::Active)
+                .unwrap_or(LicenseStatus::Revoked)
+        };
+
+        Self {
+            instance_id,
+            status,
+            validated_at: now,
+            expires_at: response.expires_at,
+            token: response.validation_token,
+            seeded: false,
+        }
+    }
+
+    // Gives an existing activation one offline grace period from the upgrade
+    fn seeded(instance_id: &str, now: u64) -> Self {
+        Self {
+            instance_id: instance_id.to_string(),
+            status: LicenseStatus::Active,
+            validated_at: now,
+            expires_at: None,
+            token: None,
+            seeded: true,
+        }
+    }
+
+    // Applies the signed claims, so editing the stored record can't extend a license.
+    // With a public key configured, a record without a valid token isn't trusted at all.
+    fn verified(mut self) -> Option<Self> {
+        let Some(public_key) = get_public_key() else {
+            return Some(self);
+        };
+
+        match self.token.as_deref().and_then(|token| verify_token(&public_key, token)) {
+            Some(claims) if claims.instance_id == self.instance_id => {
+                self.status = claims.status;
+                self.validated_at = claims.issued_at;
+                self.expires_at = claims.expires_at;
+                Some(self)
+            }
+            Some(_) => {
+                tracing::warn!("license_token_instance_mismatch");
+                None
+            }
+            None => {
+                // A revocation holds without a token, it only takes the license away
+                if self.status == LicenseStatus::Revoked || self.seeded {
+                    return Some(self);
+                }
+                tracing::warn!("license_record_unverified");
+                None
+            }
+        }
+    }
+
+    fn state(&self, now: u64, offline: bool) -> LicenseState {
+        let expired = self.expires_at.is_some_and(|expires_at| now >= expires_at);
+        let grace_ends = self.validated_at.saturating_add(OFFLINE_GRACE_PERIOD_SECS);
+        let status = match self.status {
+            LicenseStatus::Active if expired => LicenseStatus::Expired,
+            status => status,
+        };
+
+        LicenseState {
+            valid: status == LicenseStatus::Active && now < grace_ends,
+            status: Some(status),
+            offline,
+            validated_at: Some(self.validated_at),
+            expires_at: self.expires_at,
+            grace_remaining_secs: Some(grace_ends.saturating_sub(now)),
+        }
+    }
+}
+
+// Public key for validation tokens, tokens are ignored when none is configured
+fn get_public_key() -> Option<VerifyingKey> {
+    let encoded = env::var("LICENSE_PUBLIC_KEY")
+        .ok()
+        .or_else(|| option_env!("LICENSE_PUBLIC_KEY").map(str::to_string))?;
+
+    let bytes: [u8; 32] = STANDARD.decode(encoded.trim()).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+fn verify_token(public_key: &VerifyingKey, token: &str) -> Option<TokenClaims> {
+    let (payload, signature) = token.split_once('.')?;
+
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    let signature = Signature::from_slice(&signature).ok()?;
+
+    if public_key.verify_strict(&payload, &signature).is_err() {
+        tracing::warn!("license_token_signature_invalid");
+        return None;
+    }
+    serde_json::from_slice(&payload).ok()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn get_credentials(app: &AppHandle) -> PluelyResult<Option<(String, String)>> {
+    let store = secrets::store(app)?;
+    let license_key = store.get(secrets::PLUELY, secrets::LICENSE_KEY)?;
+    let instance_id = store.get(secrets::PLUELY, secrets::INSTANCE_ID)?;
+    Ok(license_key.zip(instance_id))
+}
+
+// The record for the current instance, a record left over from another activation is ignored
+fn load_record(app: &AppHandle, instance_id: &str) -> PluelyResult<Option<LicenseRecord>> {
+    let Some(content) = secrets::store(app)?.get(secrets::PLUELY, secrets::LICENSE_VALIDATION)? else {
+        // Activated before verdicts were stored, so an upgrade while offline keeps working
+        let record = LicenseRecord::seeded(instance_id, now_secs());
+        save_record(app, &record)?;
+        tracing::info!("license_record_seeded");
+        return Ok(Some(record));
+    };
+
+    let record = serde_json::from_str::<LicenseRecord>(&content)
+        .ok()
+        .filter(|record| record.instance_id == instance_id)
+        .and_then(LicenseRecord::verified);
+    Ok(record)
+}
+
+fn save_record(app: &AppHandle, record: &LicenseRecord) -> PluelyResult<()> {
+    let content = serde_json::to_string(record)
+        .map_err(|e| PluelyError::storage(format!("Failed to serialize license record: {}", e)))?;
+    secrets::store(app)?.set(secrets::PLUELY, secrets::LICENSE_VALIDATION, &content)
+}
+
+/// Stores the verdict from a successful activation, so a fresh activation
+/// doesn't need another round trip before it can be used offline.
+pub fn record_activation(app: &AppHandle, instance_id: &str, token: Option<String>) -> PluelyResult<()> {
+    let record = LicenseRecord {
+        instance_id: instance_id.to_string(),
+        status: LicenseStatus::Active,
+        validated_at: now_secs(),
+        expires_at: None,
+        token,
+        seeded: false,
+    };
+    save_record(app, &record)
+}
+
+/// License state from the cached verdict, without touching the network.
+pub fn check(app: &AppHandle) -> PluelyResult<LicenseState> {
+    let Some((_, instance_id)) = get_credentials(app)? else {
+        return Ok(LicenseState::unlicensed());
+    };
+
+    Ok(match load_record(app, &instance_id)? {
+        Some(record) => record.state(now_secs(), false),
+        None => LicenseState::unvalidated(false),
+    })
+}
+
+// Used by every licensed API call
+pub fn ensure_valid(app: &AppHandle) -> PluelyResult<()> {
+    let state = check(app)?;
+    if state.valid {
+        return Ok(());
+    }
+
+    Err(PluelyError::auth(match state.status {
+        None => "No license found. Please activate your license first.",
+        Some(LicenseStatus::Revoked) => "This license has been revoked.",
+        Some(LicenseStatus::Expired) => "This license has expired.",
+        Some(LicenseStatus::Inactive) => "This license is no longer active.",
+        Some(LicenseStatus::Active | LicenseStatus::Unvalidated) => {
+            "License could not be validated. Please connect to the internet."
+        }
+    }))
+}
+
+async fn request_validation(app: &AppHandle, license_key: &str, instance_id: &str) -> PluelyResult<ValidationResponse> {
+    let payment_endpoint = config::get_payment_endpoint(app)?;
+    let api_access_key = config::get_api_access_key(app)?;
+
+    let request = http::client(app)
+        .post(format!("{}/validate", payment_endpoint))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_access_key))
+        .json(&ValidationRequest {
+            license_key,
+            instance_id,
+        });
+
+    // Only reads the license state, so repeating it is harmless
+    let policy = RetryPolicy { idempotent: true, ..http::retry_policy(app) };
+    let response = retry::send(request, &policy)
+        .await
+        .map_err(|e| e.context("Failed to make validation request"))?;
+
+    response
+        .json()
+        .await
+        .map_err(|e| PluelyError::from(e).context("Failed to parse validation response"))
+}
+
+// The payment server couldn't be reached
+fn is_offline(error: &PluelyError) -> bool {
+    match error {
+        PluelyError::Network { .. } | PluelyError::Timeout { .. } | PluelyError::RateLimited { .. } => true,
+        PluelyError::Server { status, .. } => *status >= 500 || *status == 408,
+        _ => false,
+    }
+}
+
+// The request itself was refused, e.g. a missing route or a wrong access key. That says
+// nothing about the license, only a verdict in a successful response takes it away.
+fn is_unverifiable(error: &PluelyError) -> bool {
+    match error {
+        PluelyError::Auth { .. } | PluelyError::Parse { .. } => true,
+        PluelyError::Server { status, .. } => (400..500).contains(status),
+        _ => false,
+    }
+}
+
+// State to report and the record to save, if any, for the result of a validation request
+fn outcome(
+    result: PluelyResult<ValidationResponse>,
+    record: Option<LicenseRecord>,
+    instance_id: String,
+    now: u64,
+) -> PluelyResult<(LicenseState, Option<LicenseRecord>)> {
+    match result {
+        Ok(response) => {
+            let record = LicenseRecord::from_response(instance_id, response, now);
+            tracing::info!(status = ?record.status, "license_validated");
+            Ok((record.state(now, false), Some(record)))
+        }
+        Err(e) if is_offline(&e) || is_unverifiable(&e) => {
+            let offline = is_offline(&e);
+            tracing::warn!(error = %e, offline, "license_validation_failed");
+            let state = match record {
+                Some(record) => record.state(now, offline),
+                None => LicenseState::unvalidated(offline),
+            };
+            Ok((state, None))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Re-checks the license with the payment server when the cached verdict is older
+/// than a day, or always when `force` is set. Falls back to the cached verdict
+/// within the offline grace period when the server can't be reached or refuses the request.
+pub async fn validate(app: &AppHandle, force: bool) -> PluelyResult<LicenseState> {
+    let Some((license_key, instance_id)) = get_credentials(app)? else {
+        return Ok(LicenseState::unlicensed());
+    };
+
+    let now = now_secs();
+    let record = load_record(app, &instance_id)?;
+    if let Some(record) = &record {
+        let fresh = !record.seeded && now.saturating_sub(record.validated_at) < VALIDATION_INTERVAL_SECS;
+        if !force && fresh {
+            return Ok(record.state(now, false));
+        }
+    }
+
+    let result = request_validation(app, &license_key, &instance_id).await;
+    let (state, verdict) = outcome(result, record, instance_id, now)?;
+    if let Some(verdict) = verdict {
+        save_record(app, &verdict)?;
+    }
+    Ok(state)
+}
+
+/// Re-validates in the background and emits `license_status_changed` whenever
+/// the license becomes usable or unusable.
+pub fn spawn_periodic_validation(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut last_valid = None;
+        loop {
+            match validate(&app, false).await {
+                Ok(state) => {
+                    if last_valid != Some(state.valid) {
+                        last_valid = Some(state.valid);
+                        let _ = app.emit("license_status_changed", &state);
+                    }
+                }
+                Err(e) => eprintln!("License validation failed: {}", e),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn validate_license(app: AppHandle, force: Option<bool>) -> PluelyResult<LicenseState> {
+    validate(&app, force.unwrap_or(false)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const INSTANCE: &str = "instance-1";
+
+    fn cached() -> LicenseRecord {
+        LicenseRecord {
+            instance_id: INSTANCE.to_string(),
+            status: LicenseStatus::Active,
+            validated_at: NOW - VALIDATION_INTERVAL_SECS,
+            expires_at: None,
+            token: None,
+            seeded: false,
+        }
+    }
+
+    fn response(valid: bool, status: Option<LicenseStatus>) -> ValidationResponse {
+        ValidationResponse {
+            valid,
+            status,
+            expires_at: None,
+            validation_token: None,
+        }
+    }
+
+    fn run(result: PluelyResult<ValidationResponse>, record: Option<LicenseRecord>) -> (LicenseState, Option<LicenseRecord>) {
+        outcome(result, record, INSTANCE.to_string(), NOW).unwrap()
+    }
+
+    #[test]
+    fn refused_requests_never_revoke() {
+        for (name, error) in [
+            ("404", PluelyError::from_status(404, "Not Found", None)),
+            ("405", PluelyError::from_status(405, "Method Not Allowed", None)),
+            ("400", PluelyError::from_status(400, "Bad Request", None)),
+            ("401", PluelyError::from_status(401, "Unauthorized", None)),
+            ("403", PluelyError::from_status(403, "Forbidden", None)),
+            ("parse", PluelyError::parse("expected value")),
+        ] {
+            let (state, verdict) = run(Err(error.clone()), Some(cached()));
+            assert!(verdict.is_none(), "{}: saved a verdict", name);
+            assert!(state.valid, "{}: cached license no longer valid", name);
+            assert_eq!(state.status, Some(LicenseStatus::Active), "{}", name);
+            assert!(!state.offline, "{}", name);
+
+            let (state, verdict) = run(Err(error), None);
+            assert!(verdict.is_none(), "{}: saved a verdict", name);
+            assert_eq!(state.status, Some(LicenseStatus::Unvalidated), "{}", name);
+        }
+    }
+
+    #[test]
+    fn unreachable_server_uses_cached_verdict() {
+        for error in [
+            PluelyError::network("connection refused"),
+            PluelyError::timeout("timed out"),
+            PluelyError::from_status(503, "Service Unavailable", None),
+            PluelyError::from_status(429, "Too Many Requests", Some(30)),
+        ] {
+            let (state, verdict) = run(Err(error), Some(cached()));
+            assert!(verdict.is_none());
+            assert!(state.valid && state.offline);
+        }
+    }
+
+    #[test]
+    fn only_a_verdict_in_the_response_revokes() {
+        for (status, expected) in [
+            (None, LicenseStatus::Revoked),
+            (Some(LicenseStatus::Revoked), LicenseStatus::Revoked),
+            (Some(LicenseStatus::Expired), LicenseStatus::Expired),
+            (Some(LicenseStatus::Inactive), LicenseStatus::Inactive),
+            // `valid: false` wins over a contradicting status
+            (Some(LicenseStatus::Active), LicenseStatus::Revoked),
+        ] {
+            let (state, verdict) = run(Ok(response(false, status)), Some(cached()));
+            assert_eq!(verdict.expect("verdict saved").status, expected);
+            assert!(!state.valid);
+            assert_eq!(state.status, Some(expected));
+        }
+
+        let (state, verdict) = run(Ok(response(true, None)), None);
+        assert_eq!(verdict.expect("verdict saved").validated_at, NOW);
+        assert!(state.valid);
+    }
+
+    #[test]
+    fn other_errors_are_returned() {
+        let result = outcome(Err(PluelyError::storage("disk full")), Some(cached()), INSTANCE.to_string(), NOW);
+        assert!(matches!(result, Err(PluelyError::Storage { .. })));
+    }
+
+    #[test]
+    fn seeded_record_gets_a_grace_period() {
+        let record = LicenseRecord::seeded(INSTANCE, NOW);
+        let state = record.state(NOW, true);
+        assert!(state.valid);
+        assert_eq!(state.grace_remaining_secs, Some(OFFLINE_GRACE_PERIOD_SECS));
+        assert!(!record.state(NOW + OFFLINE_GRACE_PERIOD_SECS, true).valid);
+
+        // Records saved before the field existed aren't seeded
+        let legacy: LicenseRecord = serde_json::from_str(
+            r#"{"instance_id":"instance-1","status":"active","validated_at":1,"expires_at":null,"token":null}"#,
+        )
+        .unwrap();
+        assert!(!legacy.seeded);
+    }
+}
@@ -0,0 +1,171 @@
+// Pluely offline speech-to-text, runs whisper.cpp on the CPU so audio never leaves the machine.
+// Only compiled in with the `local-stt` cargo feature.
+#[cfg(feature = "local-stt")]
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+#[cfg(feature = "local-stt")]
+use hound::{SampleFormat, WavReader};
+#[cfg(feature = "local-stt")]
+use std::env;
+#[cfg(feature = "local-stt")]
+use std::io::Cursor;
+#[cfg(feature = "local-stt")]
+use std::path::PathBuf;
+use tauri::AppHandle;
+#[cfg(feature = "local-stt")]
+use tauri::Manager;
+
+use crate::error::{PluelyError, PluelyResult};
+#[cfg(feature = "local-stt")]
+use crate::speaker::resample::resample;
+
+// whisper.cpp only accepts 16 kHz mono
+#[cfg(feature = "local-stt")]
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+#[cfg(feature = "local-stt")]
+const MODELS_DIR: &str = "models";
+#[cfg(feature = "local-stt")]
+const DEFAULT_MODEL_FILE: &str = "ggml-base.bin";
+
+// WHISPER_MODEL_PATH overrides the model downloaded into the app data dir
+#[cfg(feature = "local-stt")]
+fn get_model_path(app: &AppHandle) -> PluelyResult<PathBuf> {
+    if let Ok(path) = env::var("WHISPER_MODEL_PATH") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let app_data_dir = app.path().app_data_dir()
+        .map_err(|e| PluelyError::storage(format!("Failed to get app data directory: {}", e)))?;
+    Ok(app_data_dir.join(MODELS_DIR).join(DEFAULT_MODEL_FILE))
+}
+
+/// Decodes a base64 WAV, as produced by `samples_to_wav_b64`, into 16 kHz mono samples.
+#[cfg(feature = "local-stt")]
+pub fn decode_wav(audio_base64: &str) -> PluelyResult<Vec<f32>> {
+    let bytes = B64
+        .decode(audio_base64.trim())
+        .map_err(|e| PluelyError::parse(format!("Invalid base64 audio: {}", e)))?;
+    let reader = WavReader::new(Cursor::new(bytes))
+        .map_err(|e| PluelyError::parse(format!("Invalid WAV audio: {}", e)))?;
+
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>(),
+        SampleFormat::Int => {
+            // Also guards the shift below, hound reads integer samples of up to 32 bits
+            if !(1..=32).contains(&spec.bits_per_sample) {
+                return Err(PluelyError::unsupported(format!(
+                    "Unsupported WAV bit depth: {}",
+                    spec.bits_per_sample
+                )));
+            }
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+        }
+    }
+    .map_err(|e| PluelyError::parse(format!("Invalid WAV samples: {}", e)))?;
+
+    // Downmix by averaging the channels of each frame
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    Ok(resample(&mono, spec.sample_rate, WHISPER_SAMPLE_RATE))
+}
+
+#[cfg(feature = "local-stt")]
+mod engine {
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+    use crate::error::{PluelyError, PluelyResult};
+
+    // Loading a model takes seconds, keep the last one around
+    static MODEL: Mutex<Option<(PathBuf, Arc<WhisperContext>)>> = Mutex::new(None);
+
+    fn load_model(path: &Path) -> PluelyResult<Arc<WhisperContext>> {
+        let mut model = MODEL.lock().unwrap();
+        if let Some((loaded_path, context)) = model.as_ref() {
+            if loaded_path == path {
+                return Ok(context.clone());
+            }
+        }
+
+        if !path.exists() {
+            return Err(PluelyError::config(format!(
+                "Whisper model not found at {}. Download a ggml model there or set WHISPER_MODEL_PATH.",
+                path.display()
+            )));
+        }
+
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| PluelyError::config("Whisper model path is not valid UTF-8"))?;
+        let context = WhisperContext::new_with_params(path_str, WhisperContextParameters::default())
+            .map_err(|e| PluelyError::audio(format!("Failed to load whisper model: {}", e)))?;
+        let context = Arc::new(context);
+
+        tracing::info!(path = %path.display(), "loaded_whisper_model");
+        *model = Some((path.to_path_buf(), context.clone()));
+        Ok(context)
+    }
+
+    pub fn transcribe(model_path: &Path, samples: &[f32]) -> PluelyResult<String> {
+        let context = load_model(model_path)?;
+        let mut state = context
+            .create_state()
+            .map_err(|e| PluelyError::audio(format!("Failed to create whisper state: {}", e)))?;
+
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get().min(8));
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_n_threads(threads as i32);
+        params.set_language(Some("auto"));
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+
+        state
+            .full(params, samples)
+            .map_err(|e| PluelyError::audio(format!("Whisper transcription failed: {}", e)))?;
+
+        let segments = state
+            .full_n_segments()
+            .map_err(|e| PluelyError::audio(format!("Failed to read transcription: {}", e)))?;
+        let mut text = String::new();
+        for i in 0..segments {
+            let segment = state
+                .full_get_segment_text(i)
+                .map_err(|e| PluelyError::audio(format!("Failed to read transcription: {}", e)))?;
+            text.push_str(&segment);
+        }
+
+        Ok(text.trim().to_string())
+    }
+}
+
+/// Transcribes a base64 WAV on this machine.
+#[cfg(feature = "local-stt")]
+pub async fn transcribe(app: &AppHandle, audio_base64: String) -> PluelyResult<String> {
+    let model_path = get_model_path(app)?;
+
+    // Inference is CPU bound, keep it off the async runtime
+    tokio::task::spawn_blocking(move || {
+        let samples = decode_wav(&audio_base64)?;
+        engine::transcribe(&model_path, &samples)
+    })
+    .await
+    .map_err(|e| PluelyError::audio(format!("Local transcription task failed: {}", e)))?
+}
+
+#[cfg(not(feature = "local-stt"))]
+pub async fn transcribe(_app: &AppHandle, _audio_base64: String) -> PluelyResult<String> {
+    Err(PluelyError::unsupported(
+        "Local transcription is not available in this build, it requires the local-stt feature",
+    ))
+}
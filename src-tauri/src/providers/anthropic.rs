@@ -0,0 +1,198 @@
+// Anthropic Messages API streaming
+use serde_json::{json, Value};
+
+use crate::error::{PluelyError, PluelyResult};
+
+use super::{
+    base_url_or, split_image_data, ChatProvider, CompletionRequest, ProviderConfig, StreamDelta,
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// The Messages API requires max_tokens on every request
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+pub struct AnthropicProvider {
+    config: ProviderConfig,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn build_body(&self, request: &CompletionRequest) -> Value {
+        let mut messages: Vec<Value> = request
+            .history
+            .iter()
+            .filter(|m| m.role == "user" || m.role == "assistant")
+            .map(|m| json!({ "role": m.role, "content": m.content }))
+            .collect();
+
+        let mut content = Vec::new();
+        for image in &request.images_base64 {
+            let (mime, data) = split_image_data(image);
+            content.push(json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": mime, "data": data }
+            }));
+        }
+        content.push(json!({ "type": "text", "text": request.user_message }));
+        messages.push(json!({ "role": "user", "content": content }));
+
+        let mut body = json!({
+            "model": self.config.model,
+            "max_tokens": self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": messages,
+            "stream": true,
+        });
+        if let Some(system_prompt) = &request.system_prompt {
+            body["system"] = json!(system_prompt);
+        }
+        if let Some(temperature) = self.config.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        body
+    }
+}
+
+impl ChatProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &CompletionRequest,
+    ) -> reqwest::RequestBuilder {
+        let url = format!("{}/messages", base_url_or(&self.config, DEFAULT_BASE_URL));
+
+        client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&self.build_body(request))
+    }
+
+    fn parse_event(&self, data: &str) -> PluelyResult<StreamDelta> {
+        let parsed: Value = serde_json::from_str(data)
+            .map_err(|e| PluelyError::from(e).context("Failed to parse stream event"))?;
+
+        match parsed.get("type").and_then(|t| t.as_str()) {
+            Some("content_block_delta") => match parsed
+                .pointer("/delta/text")
+                .and_then(|t| t.as_str())
+            {
+                Some(text) if !text.is_empty() => Ok(StreamDelta::Content(text.to_string())),
+                _ => Ok(StreamDelta::Skip),
+            },
+            Some("message_stop") => Ok(StreamDelta::Done),
+            Some("error") => Err(PluelyError::from_stream_error(data)),
+            _ => Ok(StreamDelta::Skip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_support::{built, config, header, replay, request};
+    use crate::providers::ProviderKind;
+
+    fn provider() -> AnthropicProvider {
+        AnthropicProvider::new(config(ProviderKind::Anthropic))
+    }
+
+    // Recorded from /v1/messages with stream=true
+    const RECORDED: &str = "\
+event: message_start\n\
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"test-model\",\"usage\":{\"input_tokens\":10,\"output_tokens\":1}}}\n\n\
+event: content_block_start\n\
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n\
+event: ping\n\
+data: {\"type\": \"ping\"}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" there\"}}\n\n\
+event: content_block_stop\n\
+data: {\"type\":\"content_block_stop\",\"index\":0}\n\n\
+event: message_delta\n\
+data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":5}}\n\n\
+event: message_stop\n\
+data: {\"type\":\"message_stop\"}\n\n";
+
+    #[test]
+    fn parses_recorded_stream() {
+        assert_eq!(replay(&provider(), RECORDED).unwrap(), ("Hello there".to_string(), true));
+    }
+
+    #[test]
+    fn parse_event_table() {
+        let cases = [
+            (r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#, StreamDelta::Content("Hi".to_string())),
+            (r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":""}}"#, StreamDelta::Skip),
+            // Tool input and thinking deltas carry no text
+            (r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{}"}}"#, StreamDelta::Skip),
+            (r#"{"type":"ping"}"#, StreamDelta::Skip),
+            (r#"{"type":"message_stop"}"#, StreamDelta::Done),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(provider().parse_event(data).unwrap(), expected, "{}", data);
+        }
+    }
+
+    #[test]
+    fn parse_event_errors() {
+        let overloaded = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(matches!(provider().parse_event(overloaded), Err(PluelyError::Server { status: 529, .. })));
+
+        let rate_limited = r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of request tokens has exceeded your per-minute rate limit"}}"#;
+        assert!(matches!(provider().parse_event(rate_limited), Err(PluelyError::RateLimited { .. })));
+
+        let api_error = r#"{"type":"error","error":{"type":"api_error","message":"Internal server error"}}"#;
+        let error = provider().parse_event(api_error).unwrap_err();
+        assert!(matches!(error, PluelyError::Server { status: 500, .. }));
+        assert!(error.message().contains("Internal server error"));
+
+        assert!(matches!(provider().parse_event(""), Err(PluelyError::Parse { .. })));
+    }
+
+    #[test]
+    fn builds_body() {
+        let mut request = request();
+        // Anthropic only accepts user and assistant turns in messages
+        request.history.insert(0, crate::providers::ChatMessage {
+            role: "system".to_string(),
+            content: "ignored".to_string(),
+        });
+        let body = provider().build_body(&request);
+
+        assert_eq!(body["model"], "test-model");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["stream"], true);
+
+        let messages = body["messages"].as_array().unwrap();
+        let roles: Vec<_> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+
+        // Images go before the text
+        let content = &messages[2]["content"];
+        assert_eq!(
+            content[0]["source"],
+            json!({ "type": "base64", "media_type": "image/jpeg", "data": "AAAA" })
+        );
+        assert_eq!(content[1], json!({ "type": "text", "text": "What is this?" }));
+    }
+
+    #[test]
+    fn builds_request() {
+        let request = built(&provider());
+        assert_eq!(request.url().as_str(), "https://api.anthropic.com/v1/messages");
+        assert_eq!(header(&request, "x-api-key"), Some("test-key"));
+        assert_eq!(header(&request, "anthropic-version"), Some(ANTHROPIC_VERSION));
+    }
+}
@@ -0,0 +1,53 @@
+// Pluely provider chat command, streams tagged `chat_stream_chunk` events like `api::chat_stream`
+use tauri::AppHandle;
+
+use super::{provider_for, ChatMessage, ChatProvider, CompletionRequest, ProviderConfig};
+use crate::error::{PluelyError, PluelyResult};
+use crate::http;
+use crate::retry::RetryPolicy;
+use crate::secrets;
+use crate::streams::{spawn_chat_stream, stream_response, ChatStreamSink};
+
+// Returns the request ID tagged on every stream event
+#[tauri::command]
+pub async fn provider_chat_stream(
+    app: AppHandle,
+    config: ProviderConfig,
+    user_message: String,
+    system_prompt: Option<String>,
+    images_base64: Option<Vec<String>>,
+    history: Option<Vec<ChatMessage>>,
+) -> PluelyResult<String> {
+    let mut config = config;
+    if let Some(credential) = config.credential.as_deref() {
+        config.api_key = secrets::store(&app)?
+            .get(secrets::PROVIDER, credential)?
+            .ok_or_else(|| PluelyError::auth(format!("No API key stored for {}", credential)))?;
+    }
+
+    let provider = provider_for(config);
+    let request = CompletionRequest {
+        system_prompt: system_prompt.filter(|p| !p.trim().is_empty()),
+        history: history.unwrap_or_default(),
+        user_message,
+        images_base64: images_base64.unwrap_or_default(),
+    };
+
+    let client = http::client(&app);
+    let http_request = provider.build_request(&client, &request);
+    let policy = http::retry_policy(&app);
+
+    Ok(spawn_chat_stream(&app, |sink| {
+        stream_provider_response(provider, http_request, policy, sink)
+    }))
+}
+
+async fn stream_provider_response(
+    provider: Box<dyn ChatProvider>,
+    request: reqwest::RequestBuilder,
+    policy: RetryPolicy,
+    sink: ChatStreamSink,
+) -> PluelyResult<String> {
+    let provider = provider.as_ref();
+    stream_response(request, policy, sink, provider.name(), |data| provider.parse_event(data)).await
+}
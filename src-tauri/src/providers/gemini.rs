@@ -0,0 +1,197 @@
+// Google Gemini `streamGenerateContent` streaming
+use serde_json::{json, Value};
+
+use crate::error::{PluelyError, PluelyResult};
+
+use super::{
+    base_url_or, split_image_data, ChatProvider, CompletionRequest, ProviderConfig, StreamDelta,
+};
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub struct GeminiProvider {
+    config: ProviderConfig,
+}
+
+impl GeminiProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn build_body(&self, request: &CompletionRequest) -> Value {
+        let mut contents: Vec<Value> = request
+            .history
+            .iter()
+            .filter(|m| m.role == "user" || m.role == "assistant")
+            .map(|m| {
+                // Gemini calls the assistant role "model"
+                let role = if m.role == "assistant" { "model" } else { "user" };
+                json!({ "role": role, "parts": [{ "text": m.content }] })
+            })
+            .collect();
+
+        let mut parts = vec![json!({ "text": request.user_message })];
+        for image in &request.images_base64 {
+            let (mime, data) = split_image_data(image);
+            parts.push(json!({ "inline_data": { "mime_type": mime, "data": data } }));
+        }
+        contents.push(json!({ "role": "user", "parts": parts }));
+
+        let mut body = json!({ "contents": contents });
+        if let Some(system_prompt) = &request.system_prompt {
+            body["systemInstruction"] = json!({ "parts": [{ "text": system_prompt }] });
+        }
+
+        let mut generation_config = json!({});
+        if let Some(max_tokens) = self.config.max_tokens {
+            generation_config["maxOutputTokens"] = json!(max_tokens);
+        }
+        if let Some(temperature) = self.config.temperature {
+            generation_config["temperature"] = json!(temperature);
+        }
+        if generation_config.as_object().is_some_and(|o| !o.is_empty()) {
+            body["generationConfig"] = generation_config;
+        }
+        body
+    }
+}
+
+impl ChatProvider for GeminiProvider {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &CompletionRequest,
+    ) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/models/{}:streamGenerateContent?alt=sse",
+            base_url_or(&self.config, DEFAULT_BASE_URL),
+            self.config.model
+        );
+
+        client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-key", &self.config.api_key)
+            .json(&self.build_body(request))
+    }
+
+    fn parse_event(&self, data: &str) -> PluelyResult<StreamDelta> {
+        let parsed: Value = serde_json::from_str(data)
+            .map_err(|e| PluelyError::from(e).context("Failed to parse stream event"))?;
+
+        if parsed.pointer("/error/message").is_some() {
+            return Err(PluelyError::from_stream_error(data));
+        }
+
+        // A chunk may carry several text parts; Gemini has no [DONE] marker
+        let text: String = parsed
+            .pointer("/candidates/0/content/parts")
+            .and_then(|p| p.as_array())
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if text.is_empty() {
+            Ok(StreamDelta::Skip)
+        } else {
+            Ok(StreamDelta::Content(text))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_support::{built, config, header, replay, request};
+    use crate::providers::ProviderKind;
+
+    fn provider() -> GeminiProvider {
+        GeminiProvider::new(config(ProviderKind::Gemini))
+    }
+
+    // Recorded from :streamGenerateContent?alt=sse, which has no end marker
+    const RECORDED: &str = "\
+data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"Hello\"}],\"role\": \"model\"},\"index\": 0}],\"usageMetadata\": {\"promptTokenCount\": 8,\"totalTokenCount\": 8},\"modelVersion\": \"test-model\"}\r\n\r\n\
+data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \" there\"}],\"role\": \"model\"},\"finishReason\": \"STOP\",\"index\": 0}],\"usageMetadata\": {\"promptTokenCount\": 8,\"candidatesTokenCount\": 2,\"totalTokenCount\": 10},\"modelVersion\": \"test-model\"}\r\n\r\n";
+
+    #[test]
+    fn parses_recorded_stream() {
+        assert_eq!(replay(&provider(), RECORDED).unwrap(), ("Hello there".to_string(), false));
+    }
+
+    #[test]
+    fn parse_event_table() {
+        let cases = [
+            (r#"{"candidates":[{"content":{"parts":[{"text":"Hi"}],"role":"model"}}]}"#, StreamDelta::Content("Hi".to_string())),
+            // Several parts in one chunk are joined
+            (r#"{"candidates":[{"content":{"parts":[{"text":"a"},{"text":"b"}],"role":"model"}}]}"#, StreamDelta::Content("ab".to_string())),
+            (r#"{"candidates":[{"content":{"parts":[{"text":""}],"role":"model"},"finishReason":"STOP"}]}"#, StreamDelta::Skip),
+            // Blocked prompts come back without candidates
+            (r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#, StreamDelta::Skip),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(provider().parse_event(data).unwrap(), expected, "{}", data);
+        }
+    }
+
+    #[test]
+    fn parse_event_errors() {
+        let exhausted = r#"{"error":{"code":429,"message":"Resource has been exhausted (e.g. check quota).","status":"RESOURCE_EXHAUSTED"}}"#;
+        assert!(matches!(provider().parse_event(exhausted), Err(PluelyError::RateLimited { .. })));
+
+        let unavailable = r#"{"error":{"code":503,"message":"The model is overloaded. Please try again later.","status":"UNAVAILABLE"}}"#;
+        assert!(matches!(provider().parse_event(unavailable), Err(PluelyError::Server { status: 503, .. })));
+
+        let invalid = r#"{"error":{"code":400,"message":"Request contains an invalid argument.","status":"INVALID_ARGUMENT"}}"#;
+        assert!(matches!(provider().parse_event(invalid), Err(PluelyError::Server { status: 400, .. })));
+
+        let denied = r#"{"error":{"code":403,"message":"Permission denied on resource project.","status":"PERMISSION_DENIED"}}"#;
+        assert!(matches!(provider().parse_event(denied), Err(PluelyError::Auth { .. })));
+
+        assert!(matches!(provider().parse_event("data"), Err(PluelyError::Parse { .. })));
+    }
+
+    #[test]
+    fn builds_body() {
+        let body = provider().build_body(&request());
+
+        assert_eq!(body["systemInstruction"], json!({ "parts": [{ "text": "Be brief." }] }));
+        assert!(body.get("generationConfig").is_none());
+
+        let contents = body["contents"].as_array().unwrap();
+        let roles: Vec<_> = contents.iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["user", "model", "user"]);
+
+        let parts = &contents[2]["parts"];
+        assert_eq!(parts[0], json!({ "text": "What is this?" }));
+        assert_eq!(parts[1], json!({ "inline_data": { "mime_type": "image/jpeg", "data": "AAAA" } }));
+    }
+
+    #[test]
+    fn builds_generation_config() {
+        let mut config = config(ProviderKind::Gemini);
+        config.max_tokens = Some(256);
+        config.temperature = Some(0.5);
+
+        let body = GeminiProvider::new(config).build_body(&request());
+        assert_eq!(body["generationConfig"], json!({ "maxOutputTokens": 256, "temperature": 0.5 }));
+    }
+
+    #[test]
+    fn builds_request() {
+        let request = built(&provider());
+        assert_eq!(
+            request.url().as_str(),
+            "https://generativelanguage.googleapis.com/v1beta/models/test-model:streamGenerateContent?alt=sse"
+        );
+        assert_eq!(header(&request, "x-goog-api-key"), Some("test-key"));
+    }
+}
@@ -0,0 +1,167 @@
+// Pluely native chat providers (OpenAI-compatible, Anthropic Messages, Gemini)
+use serde::{Deserialize, Serialize};
+
+use crate::error::PluelyResult;
+
+mod anthropic;
+mod gemini;
+mod openai;
+
+mod commands;
+pub use commands::*;
+
+use anthropic::AnthropicProvider;
+use gemini::GeminiProvider;
+use openai::OpenAiProvider;
+
+pub use openai::parse_event as parse_openai_event;
+
+// Which wire protocol a provider speaks
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[serde(alias = "openai-compatible")]
+    OpenAi,
+    Anthropic,
+    Gemini,
+}
+
+// Provider selection and credentials sent from the settings screen
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    #[serde(default)]
+    pub api_key: String,
+    // Name of a secret in the `provider` namespace, used instead of `api_key`
+    // so the key never has to pass through the webview
+    pub credential: Option<String>,
+    pub model: String,
+    pub base_url: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+// A single completion request, independent of the provider wire format
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub system_prompt: Option<String>,
+    pub history: Vec<ChatMessage>,
+    pub user_message: String,
+    pub images_base64: Vec<String>,
+}
+
+// What a provider extracted from one streamed event
+#[derive(Debug, PartialEq)]
+pub enum StreamDelta {
+    Content(String),
+    Done,
+    Skip,
+}
+
+pub trait ChatProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Builds the streaming HTTP request for this provider
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &CompletionRequest,
+    ) -> reqwest::RequestBuilder;
+
+    // Parses the data of one server-sent event
+    fn parse_event(&self, data: &str) -> PluelyResult<StreamDelta>;
+}
+
+pub fn provider_for(config: ProviderConfig) -> Box<dyn ChatProvider> {
+    match config.kind {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider::new(config)),
+        ProviderKind::Anthropic => Box::new(AnthropicProvider::new(config)),
+        ProviderKind::Gemini => Box::new(GeminiProvider::new(config)),
+    }
+}
+
+// Strips a `data:image/...;base64,` prefix and returns (mime type, payload)
+fn split_image_data(image: &str) -> (&str, &str) {
+    if let Some(rest) = image.strip_prefix("data:") {
+        if let Some((mime, data)) = rest.split_once(";base64,") {
+            return (mime, data);
+        }
+    }
+    ("image/png", image)
+}
+
+fn base_url_or<'a>(config: &'a ProviderConfig, default: &'a str) -> &'a str {
+    config
+        .base_url
+        .as_deref()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or(default)
+        .trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod test_support {
+    use super::*;
+    use crate::sse::SseDecoder;
+
+    pub fn config(kind: ProviderKind) -> ProviderConfig {
+        ProviderConfig {
+            kind,
+            api_key: "test-key".to_string(),
+            credential: None,
+            model: "test-model".to_string(),
+            base_url: None,
+            max_tokens: None,
+            temperature: None,
+        }
+    }
+
+    pub fn request() -> CompletionRequest {
+        CompletionRequest {
+            system_prompt: Some("Be brief.".to_string()),
+            history: vec![
+                ChatMessage { role: "user".to_string(), content: "Hi".to_string() },
+                ChatMessage { role: "assistant".to_string(), content: "Hello!".to_string() },
+            ],
+            user_message: "What is this?".to_string(),
+            images_base64: vec!["data:image/jpeg;base64,AAAA".to_string()],
+        }
+    }
+
+    /// Runs a recorded response through the SSE decoder and the provider like the
+    /// stream command does, returning the text and whether the provider saw the end.
+    pub fn replay(provider: &dyn ChatProvider, recorded: &str) -> PluelyResult<(String, bool)> {
+        let mut decoder = SseDecoder::new();
+        let mut events = decoder.feed(recorded.as_bytes());
+        events.extend(decoder.finish());
+
+        let mut text = String::new();
+        for event in events.into_iter().filter(|event| !event.data.is_empty()) {
+            match provider.parse_event(&event.data)? {
+                StreamDelta::Content(content) => text.push_str(&content),
+                StreamDelta::Done => return Ok((text, true)),
+                StreamDelta::Skip => {}
+            }
+        }
+        Ok((text, false))
+    }
+
+    // The built request, to check the URL and headers
+    pub fn built(provider: &dyn ChatProvider) -> reqwest::Request {
+        provider
+            .build_request(&reqwest::Client::new(), &request())
+            .build()
+            .expect("valid request")
+    }
+
+    pub fn header<'a>(request: &'a reqwest::Request, name: &str) -> Option<&'a str> {
+        request.headers().get(name).and_then(|value| value.to_str().ok())
+    }
+}
@@ -0,0 +1,211 @@
+// OpenAI-compatible `/chat/completions` streaming (OpenAI, Groq, OpenRouter, Ollama, ...)
+use serde_json::{json, Value};
+
+use crate::error::{PluelyError, PluelyResult};
+
+use super::{
+    base_url_or, split_image_data, ChatProvider, CompletionRequest, ProviderConfig, StreamDelta,
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+pub struct OpenAiProvider {
+    config: ProviderConfig,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn build_body(&self, request: &CompletionRequest) -> Value {
+        let mut messages = Vec::new();
+
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+
+        for message in &request.history {
+            messages.push(json!({ "role": message.role, "content": message.content }));
+        }
+
+        if request.images_base64.is_empty() {
+            messages.push(json!({ "role": "user", "content": request.user_message }));
+        } else {
+            let mut content = vec![json!({ "type": "text", "text": request.user_message })];
+            for image in &request.images_base64 {
+                let (mime, data) = split_image_data(image);
+                content.push(json!({
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", mime, data) }
+                }));
+            }
+            messages.push(json!({ "role": "user", "content": content }));
+        }
+
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "stream": true,
+        });
+        if let Some(max_tokens) = self.config.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if let Some(temperature) = self.config.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        body
+    }
+}
+
+impl ChatProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn build_request(
+        &self,
+        client: &reqwest::Client,
+        request: &CompletionRequest,
+    ) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/chat/completions",
+            base_url_or(&self.config, DEFAULT_BASE_URL)
+        );
+
+        let mut builder = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(&self.build_body(request));
+        // Local servers (Ollama, LM Studio) don't need a key
+        if !self.config.api_key.is_empty() {
+            builder = builder.bearer_auth(&self.config.api_key);
+        }
+        builder
+    }
+
+    fn parse_event(&self, data: &str) -> PluelyResult<StreamDelta> {
+        parse_event(data)
+    }
+}
+
+/// Parses one `chat.completion.chunk` event, also used for the Pluely API's own stream.
+pub fn parse_event(data: &str) -> PluelyResult<StreamDelta> {
+    if data == "[DONE]" {
+        return Ok(StreamDelta::Done);
+    }
+
+    let parsed: Value = serde_json::from_str(data)
+        .map_err(|e| PluelyError::from(e).context("Failed to parse stream event"))?;
+
+    if parsed.pointer("/error/message").is_some() {
+        return Err(PluelyError::from_stream_error(data));
+    }
+
+    match parsed
+        .pointer("/choices/0/delta/content")
+        .and_then(|c| c.as_str())
+    {
+        Some(content) if !content.is_empty() => Ok(StreamDelta::Content(content.to_string())),
+        _ => Ok(StreamDelta::Skip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_support::{built, config, header, replay, request};
+    use crate::providers::ProviderKind;
+
+    fn provider() -> OpenAiProvider {
+        OpenAiProvider::new(config(ProviderKind::OpenAi))
+    }
+
+    // Recorded from /v1/chat/completions with stream=true
+    const RECORDED: &str = "\
+data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\" there\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n\
+data: [DONE]\n\n";
+
+    #[test]
+    fn parses_recorded_stream() {
+        assert_eq!(replay(&provider(), RECORDED).unwrap(), ("Hello there".to_string(), true));
+    }
+
+    #[test]
+    fn parse_event_table() {
+        let cases = [
+            ("[DONE]", StreamDelta::Done),
+            (r#"{"choices":[{"delta":{"content":"Hi"}}]}"#, StreamDelta::Content("Hi".to_string())),
+            (r#"{"choices":[{"delta":{"content":""}}]}"#, StreamDelta::Skip),
+            (r#"{"choices":[{"delta":{"role":"assistant"}}]}"#, StreamDelta::Skip),
+            // Usage chunk sent last with stream_options.include_usage
+            (r#"{"choices":[],"usage":{"total_tokens":12}}"#, StreamDelta::Skip),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(provider().parse_event(data).unwrap(), expected, "{}", data);
+        }
+    }
+
+    #[test]
+    fn parse_event_errors() {
+        let rate_limited = r#"{"error":{"message":"Rate limit reached for requests","type":"requests","code":"rate_limit_exceeded"}}"#;
+        assert!(matches!(provider().parse_event(rate_limited), Err(PluelyError::RateLimited { .. })));
+
+        let server = r#"{"error":{"message":"The server had an error","type":"server_error","code":null}}"#;
+        assert!(matches!(provider().parse_event(server), Err(PluelyError::Server { status: 500, .. })));
+
+        let auth = r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","code":"invalid_api_key"}}"#;
+        assert!(matches!(provider().parse_event(auth), Err(PluelyError::Auth { .. })));
+
+        assert!(matches!(provider().parse_event("{not json"), Err(PluelyError::Parse { .. })));
+    }
+
+    #[test]
+    fn builds_body() {
+        let body = provider().build_body(&request());
+        assert_eq!(body["model"], "test-model");
+        assert_eq!(body["stream"], true);
+        assert!(body.get("max_tokens").is_none());
+
+        let messages = body["messages"].as_array().unwrap();
+        let roles: Vec<_> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert_eq!(messages[0]["content"], "Be brief.");
+
+        let content = &messages[3]["content"];
+        assert_eq!(content[0], json!({ "type": "text", "text": "What is this?" }));
+        assert_eq!(content[1]["image_url"]["url"], "data:image/jpeg;base64,AAAA");
+    }
+
+    #[test]
+    fn builds_body_with_options_and_plain_text() {
+        let mut config = config(ProviderKind::OpenAi);
+        config.max_tokens = Some(256);
+        config.temperature = Some(0.5);
+        let mut request = request();
+        request.images_base64.clear();
+
+        let body = OpenAiProvider::new(config).build_body(&request);
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["messages"][3]["content"], "What is this?");
+    }
+
+    #[test]
+    fn builds_request() {
+        let request = built(&provider());
+        assert_eq!(request.url().as_str(), "https://api.openai.com/v1/chat/completions");
+        assert_eq!(header(&request, "authorization"), Some("Bearer test-key"));
+
+        // Local servers get no Authorization header
+        let mut config = config(ProviderKind::OpenAi);
+        config.api_key.clear();
+        config.base_url = Some("http://localhost:11434/v1/".to_string());
+        let request = built(&OpenAiProvider::new(config));
+        assert_eq!(request.url().as_str(), "http://localhost:11434/v1/chat/completions");
+        assert_eq!(header(&request, "authorization"), None);
+    }
+}
@@ -0,0 +1,162 @@
+// Pluely request executor, retries transient failures with jittered exponential backoff
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::{retry_after_secs, PluelyError, PluelyResult};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(8);
+// Longer Retry-After values are surfaced to the user instead of waited out
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Whether repeating the request is harmless. Non-idempotent requests are only
+    /// retried when the server never processed them (connect errors, 429, 503).
+    /// Off by default, only calls without side effects opt in.
+    pub idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Backoff before retry number `attempt` (1-based), with equal jitter
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        exponential.mul_f64(0.5 + fastrand::f64() / 2.0)
+    }
+}
+
+// A failed attempt and whether it may be repeated
+#[derive(Debug)]
+pub struct Failure {
+    error: PluelyError,
+    retryable: bool,
+    retry_after: Option<u64>,
+}
+
+impl Failure {
+    // Never retried, e.g. a stream that already emitted chunks
+    pub fn fatal(error: PluelyError) -> Self {
+        Self {
+            error,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    pub fn context(mut self, context: &str) -> Self {
+        self.error = self.error.context(context);
+        self
+    }
+}
+
+impl From<PluelyError> for Failure {
+    fn from(error: PluelyError) -> Self {
+        let (retryable, retry_after) = match &error {
+            PluelyError::Network { .. } | PluelyError::Timeout { .. } => (true, None),
+            PluelyError::RateLimited { retry_after, .. } => (true, *retry_after),
+            // 529 is an overloaded provider reported inside the stream
+            PluelyError::Server { status, .. } => (matches!(status, 502..=504 | 529), None),
+            _ => (false, None),
+        };
+        Self {
+            error,
+            retryable,
+            retry_after,
+        }
+    }
+}
+
+/// Runs `operation` until it succeeds, fails permanently or runs out of retries.
+/// Honors the server's Retry-After when one was sent.
+pub async fn with_retry<T, F, Fut>(policy: &RetryPolicy, mut operation: F) -> PluelyResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Failure>>,
+{
+    let mut attempt = 0;
+    loop {
+        let failure = match operation().await {
+            Ok(value) => return Ok(value),
+            Err(failure) => failure,
+        };
+
+        attempt += 1;
+        if !failure.retryable || attempt > policy.max_retries {
+            return Err(failure.error);
+        }
+
+        let delay = match failure.retry_after.map(Duration::from_secs) {
+            Some(retry_after) if retry_after > MAX_RETRY_AFTER => return Err(failure.error),
+            Some(retry_after) => retry_after,
+            None => policy.backoff(attempt),
+        };
+        tracing::warn!(attempt, ?delay, error = %failure.error, "retrying request");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Sends a request with retries and returns the successful response.
+/// Non-success statuses are turned into a `PluelyError`.
+pub async fn send(request: reqwest::RequestBuilder, policy: &RetryPolicy) -> PluelyResult<reqwest::Response> {
+    with_retry(policy, || {
+        let request = try_clone(&request);
+        async move { send_once(request?, policy).await }
+    })
+    .await
+}
+
+// Requests with streaming bodies can't be repeated
+pub fn try_clone(request: &reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder, Failure> {
+    request
+        .try_clone()
+        .ok_or_else(|| Failure::fatal(PluelyError::unsupported("Request body can't be retried")))
+}
+
+/// A single attempt, classifying the failure for `with_retry`.
+pub async fn send_once(request: reqwest::RequestBuilder, policy: &RetryPolicy) -> Result<reqwest::Response, Failure> {
+    let response = request.send().await.map_err(|e| {
+        // Connect errors never reached the server, anything else might have
+        let retryable = e.is_connect() || (policy.idempotent && (e.is_timeout() || e.is_request()));
+        Failure {
+            error: PluelyError::from(e),
+            retryable,
+            retry_after: None,
+        }
+    })?;
+
+    let status = response.status().as_u16();
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = retry_after_secs(response.headers());
+    let error = PluelyError::from_response(response).await;
+    let retryable = match status {
+        429 | 503 => true,
+        502 | 504 => policy.idempotent,
+        _ => false,
+    };
+    Err(Failure {
+        error,
+        retryable,
+        retry_after,
+    })
+}
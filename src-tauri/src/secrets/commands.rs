@@ -0,0 +1,45 @@
+// Pluely secret store commands, namespaced so provider and STT credentials can live in the backend.
+// Values only go in: nothing here returns a secret to the webview.
+use tauri::AppHandle;
+
+use super::{store, SecretInfo, PROVIDER, STT};
+use crate::error::{PluelyError, PluelyResult};
+
+// License secrets are only written by the license commands, so a revoked validation can't be reset from the webview
+const WRITABLE_NAMESPACES: [&str; 2] = [PROVIDER, STT];
+
+fn check_writable(namespace: &str) -> PluelyResult<()> {
+    if WRITABLE_NAMESPACES.contains(&namespace) {
+        Ok(())
+    } else {
+        Err(PluelyError::config(format!("Secrets in namespace {} can't be changed", namespace)))
+    }
+}
+
+#[tauri::command]
+pub fn secret_set(app: AppHandle, namespace: String, name: String, value: String) -> PluelyResult<()> {
+    check_writable(&namespace)?;
+    store(&app)?.set(&namespace, &name, &value)
+}
+
+#[tauri::command]
+pub fn secret_remove(app: AppHandle, namespace: String, name: String) -> PluelyResult<()> {
+    check_writable(&namespace)?;
+    store(&app)?.remove(&namespace, &name)
+}
+
+#[tauri::command]
+pub fn secret_exists(app: AppHandle, namespace: String, name: String) -> PluelyResult<bool> {
+    store(&app)?.exists(&namespace, &name)
+}
+
+#[tauri::command]
+pub fn secret_list(app: AppHandle, namespace: String) -> PluelyResult<Vec<SecretInfo>> {
+    store(&app)?.list(&namespace)
+}
+
+#[tauri::command]
+pub fn secret_rotate(app: AppHandle, namespace: String, name: String, value: String) -> PluelyResult<()> {
+    check_writable(&namespace)?;
+    store(&app)?.rotate(&namespace, &name, &value)
+}
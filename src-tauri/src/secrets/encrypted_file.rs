@@ -0,0 +1,160 @@
+// Encrypted file backend for systems without a usable keychain (e.g. headless Linux)
+// AES-256-GCM, keyed from the machine ID so the file is useless when copied elsewhere.
+// Without a machine ID (containers lacking /etc/machine-id) a random key is kept next to the file.
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::SecretStore;
+use crate::atomic_file;
+use crate::error::{PluelyError, PluelyResult};
+
+const KEY_CONTEXT: &[u8] = b"pluely-secure-storage-v1";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    // Decrypted secrets, written back to disk on every change. Held for the whole
+    // read-modify-write so concurrent commands can't lose each other's updates.
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl EncryptedFileStore {
+    pub fn open(path: PathBuf) -> PluelyResult<Self> {
+        let key = storage_key(&path)?;
+        let cipher = Aes256Gcm::new(&key);
+
+        let store = Self {
+            path,
+            cipher,
+            secrets: Mutex::new(HashMap::new()),
+        };
+        let secrets = store.read()?;
+        *store.secrets.lock().unwrap() = secrets;
+
+        Ok(store)
+    }
+
+    fn read(&self) -> PluelyResult<HashMap<String, String>> {
+        // A corrupted file falls back to the last good backup, or starts empty
+        let secrets = atomic_file::read_with_recovery(&self.path, |content| self.decrypt(content))?;
+        Ok(secrets.unwrap_or_default())
+    }
+
+    fn decrypt(&self, content: &[u8]) -> PluelyResult<HashMap<String, String>> {
+        if content.len() < NONCE_LEN {
+            return Err(PluelyError::storage("Storage file is truncated"));
+        }
+
+        let (nonce, ciphertext) = content.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| PluelyError::storage("Failed to decrypt storage file"))?;
+
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| PluelyError::storage(format!("Failed to parse storage file: {}", e)))
+    }
+
+    fn write(&self, secrets: &HashMap<String, String>) -> PluelyResult<()> {
+        let plaintext = serde_json::to_vec(secrets)
+            .map_err(|e| PluelyError::storage(format!("Failed to serialize storage: {}", e)))?;
+
+        // Fresh nonce for every write, GCM must never reuse one with the same key
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| PluelyError::storage("Failed to encrypt storage"))?;
+
+        let mut content = nonce.to_vec();
+        content.extend_from_slice(&ciphertext);
+        atomic_file::write(&self.path, &content)
+    }
+}
+
+// A key file, once created, keeps being used so the store stays readable if a machine ID shows up later
+fn storage_key(path: &Path) -> PluelyResult<Key<Aes256Gcm>> {
+    let key_path = path.with_extension("key");
+    if key_path.exists() {
+        return read_key_file(&key_path);
+    }
+
+    match machine_uid::get() {
+        Ok(machine_id) => {
+            let mut hasher = Sha256::new();
+            hasher.update(KEY_CONTEXT);
+            hasher.update(machine_id.trim().as_bytes());
+            Ok(hasher.finalize())
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, path = %key_path.display(), "machine_id_unavailable_using_key_file");
+            create_key_file(&key_path)
+        }
+    }
+}
+
+fn read_key_file(key_path: &Path) -> PluelyResult<Key<Aes256Gcm>> {
+    let bytes = fs::read(key_path)
+        .map_err(|e| PluelyError::storage(format!("Failed to read storage key: {}", e)))?;
+    if bytes.len() != KEY_LEN {
+        return Err(PluelyError::storage("Storage key file is corrupted"));
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&bytes))
+}
+
+fn create_key_file(key_path: &Path) -> PluelyResult<Key<Aes256Gcm>> {
+    let key = Aes256Gcm::generate_key(&mut OsRng);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // Only the current user may read the key
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(key_path)
+        .map_err(|e| PluelyError::storage(format!("Failed to create storage key: {}", e)))?;
+    file.write_all(&key)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| PluelyError::storage(format!("Failed to write storage key: {}", e)))?;
+
+    Ok(key)
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn name(&self) -> &'static str {
+        "encrypted_file"
+    }
+
+    fn get(&self, key: &str) -> PluelyResult<Option<String>> {
+        Ok(self.secrets.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> PluelyResult<()> {
+        let mut secrets = self.secrets.lock().unwrap();
+        secrets.insert(key.to_string(), value.to_string());
+        self.write(&secrets)
+    }
+
+    fn remove(&self, key: &str) -> PluelyResult<()> {
+        let mut secrets = self.secrets.lock().unwrap();
+        if secrets.remove(key).is_some() {
+            self.write(&secrets)?;
+            // The backup still holds the removed secret
+            atomic_file::refresh_backup(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn keys(&self) -> PluelyResult<Option<Vec<String>>> {
+        Ok(Some(self.secrets.lock().unwrap().keys().cloned().collect()))
+    }
+}
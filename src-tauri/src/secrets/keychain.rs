@@ -0,0 +1,53 @@
+// OS keychain backend (macOS Keychain, Windows Credential Manager, Secret Service on Linux)
+use keyring::Entry;
+
+use super::SecretStore;
+use crate::error::{PluelyError, PluelyResult};
+
+const SERVICE: &str = "com.srikanthnani.pluely";
+const PROBE_KEY: &str = "pluely_keychain_probe";
+
+pub struct KeychainStore;
+
+impl KeychainStore {
+    // Checks the platform keychain can actually be reached before using it
+    pub fn probe() -> PluelyResult<Self> {
+        match entry(PROBE_KEY)?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(Self),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
+
+fn entry(key: &str) -> PluelyResult<Entry> {
+    Entry::new(SERVICE, key).map_err(keychain_error)
+}
+
+fn keychain_error(e: keyring::Error) -> PluelyError {
+    PluelyError::storage(format!("Keychain error: {}", e))
+}
+
+impl SecretStore for KeychainStore {
+    fn name(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn get(&self, key: &str) -> PluelyResult<Option<String>> {
+        match entry(key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> PluelyResult<()> {
+        entry(key)?.set_password(value).map_err(keychain_error)
+    }
+
+    fn remove(&self, key: &str) -> PluelyResult<()> {
+        match entry(key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+// Pluely secret storage, keeps license and provider credentials out of plaintext files
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::{PluelyError, PluelyResult};
+
+mod encrypted_file;
+mod keychain;
+
+mod commands;
+pub use commands::*;
+
+use encrypted_file::EncryptedFileStore;
+use keychain::KeychainStore;
+
+// Plaintext file written by versions before the secret store, migrated once
+const LEGACY_STORAGE_FILE: &str = "secure_storage.json";
+const ENCRYPTED_STORAGE_FILE: &str = "secure_storage.enc";
+
+// Names and timestamps of every secret, backends like the keychain can't enumerate entries
+const INDEX_KEY: &str = "pluely_secret_index";
+// Bumped when the key layout changes, so older layouts can be migrated
+const SCHEMA_VERSION: u32 = 1;
+
+// Namespaces
+pub const PLUELY: &str = "pluely";
+pub const PROVIDER: &str = "provider";
+pub const STT: &str = "stt";
+// Access keys of endpoint profiles, keyed by profile name
+pub const ENDPOINT: &str = "endpoint";
+// License secrets of inactive endpoint profiles, keyed `<profile>.<secret>`
+pub const PROFILE_LICENSE: &str = "profile_license";
+
+// Secrets in the `pluely` namespace
+pub const LICENSE_KEY: &str = "license_key";
+pub const INSTANCE_ID: &str = "instance_id";
+pub const SELECTED_PLUELY_MODEL: &str = "selected_pluely_model";
+pub const LICENSE_VALIDATION: &str = "license_validation";
+// Everything tied to one activation, forgotten together
+pub const LICENSE_SECRETS: [&str; 4] = [LICENSE_KEY, INSTANCE_ID, SELECTED_PLUELY_MODEL, LICENSE_VALIDATION];
+
+const MAX_NAME_LEN: usize = 128;
+
+// Raw storage backend, addressed by full key
+pub trait SecretStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get(&self, key: &str) -> PluelyResult<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> PluelyResult<()>;
+    fn remove(&self, key: &str) -> PluelyResult<()>;
+
+    // Every stored key, used to rebuild a corrupted index. None when the backend can't enumerate.
+    fn keys(&self) -> PluelyResult<Option<Vec<String>>> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub name: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub rotated_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecretIndex {
+    schema_version: u32,
+    // Keyed by "<namespace>/<name>"
+    entries: BTreeMap<String, SecretInfo>,
+}
+
+impl Default for SecretIndex {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+// Managed secret store, the keychain when available, the encrypted file otherwise
+pub struct Secrets {
+    backend: Box<dyn SecretStore>,
+    // Also serializes every change to the backend
+    index: Mutex<SecretIndex>,
+}
+
+impl Secrets {
+    fn new(backend: Box<dyn SecretStore>) -> PluelyResult<Self> {
+        let index = match backend.get(INDEX_KEY)? {
+            Some(content) => match serde_json::from_str(&content) {
+                Ok(index) => index,
+                // The secrets themselves are intact, an empty index would hide them and drop them on the next write
+                Err(e) => {
+                    eprintln!("Secret index is corrupted, rebuilding it: {}", e);
+                    rebuild_index(backend.as_ref())?
+                }
+            },
+            None => SecretIndex::default(),
+        };
+
+        Ok(Self {
+            backend,
+            index: Mutex::new(index),
+        })
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    pub fn get(&self, namespace: &str, name: &str) -> PluelyResult<Option<String>> {
+        let key = secret_key(namespace, name)?;
+        let _index = self.index.lock().unwrap();
+        self.backend.get(&key)
+    }
+
+    pub fn set(&self, namespace: &str, name: &str, value: &str) -> PluelyResult<()> {
+        let key = secret_key(namespace, name)?;
+        let mut index = self.index.lock().unwrap();
+
+        self.backend.set(&key, value)?;
+        let now = now_secs();
+        index
+            .entries
+            .entry(key)
+            .and_modify(|info| info.updated_at = now)
+            .or_insert_with(|| SecretInfo {
+                name: name.to_string(),
+                created_at: now,
+                updated_at: now,
+                rotated_at: None,
+            });
+        self.save_index(&index)
+    }
+
+    pub fn remove(&self, namespace: &str, name: &str) -> PluelyResult<()> {
+        let key = secret_key(namespace, name)?;
+        let mut index = self.index.lock().unwrap();
+
+        self.backend.remove(&key)?;
+        if index.entries.remove(&key).is_some() {
+            self.save_index(&index)?;
+        }
+        Ok(())
+    }
+
+    pub fn exists(&self, namespace: &str, name: &str) -> PluelyResult<bool> {
+        let key = secret_key(namespace, name)?;
+        Ok(self.index.lock().unwrap().entries.contains_key(&key))
+    }
+
+    // Names and timestamps only, values never leave the store through a listing
+    pub fn list(&self, namespace: &str) -> PluelyResult<Vec<SecretInfo>> {
+        validate_segment("namespace", namespace)?;
+        let prefix = format!("{}/", namespace);
+
+        Ok(self
+            .index
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, info)| info.clone())
+            .collect())
+    }
+
+    // Replaces an existing secret, e.g. after regenerating an API key
+    pub fn rotate(&self, namespace: &str, name: &str, value: &str) -> PluelyResult<()> {
+        let key = secret_key(namespace, name)?;
+        let mut index = self.index.lock().unwrap();
+
+        let Some(info) = index.entries.get_mut(&key) else {
+            return Err(PluelyError::storage(format!("Secret not found: {}", key)));
+        };
+        self.backend.set(&key, value)?;
+        let now = now_secs();
+        info.updated_at = now;
+        info.rotated_at = Some(now);
+        self.save_index(&index)
+    }
+
+    fn save_index(&self, index: &SecretIndex) -> PluelyResult<()> {
+        write_index(self.backend.as_ref(), index)
+    }
+}
+
+fn write_index(backend: &dyn SecretStore, index: &SecretIndex) -> PluelyResult<()> {
+    let content = serde_json::to_string(index)
+        .map_err(|e| PluelyError::storage(format!("Failed to serialize secret index: {}", e)))?;
+    backend.set(INDEX_KEY, &content)
+}
+
+// Lists the backend's keys into a new index, the original timestamps are lost
+fn rebuild_index(backend: &dyn SecretStore) -> PluelyResult<SecretIndex> {
+    let keys = backend.keys()?.ok_or_else(|| {
+        PluelyError::storage(format!(
+            "Secret index is corrupted and the {} backend can't be listed to rebuild it",
+            backend.name()
+        ))
+    })?;
+
+    let now = now_secs();
+    let mut index = SecretIndex::default();
+    for key in keys {
+        let Some((_, name)) = key.split_once('/') else { continue };
+        index.entries.insert(
+            key.clone(),
+            SecretInfo {
+                name: name.to_string(),
+                created_at: now,
+                updated_at: now,
+                rotated_at: None,
+            },
+        );
+    }
+
+    write_index(backend, &index)?;
+    tracing::warn!(backend = backend.name(), entries = index.entries.len(), "rebuilt_secret_index");
+    Ok(index)
+}
+
+/// Opens the secret store at startup and migrates older layouts into it.
+pub fn open(app: &AppHandle) -> PluelyResult<Secrets> {
+    let app_data_dir = get_app_data_dir(app)?;
+
+    let backend: Box<dyn SecretStore> = match KeychainStore::probe() {
+        Ok(store) => Box::new(store),
+        Err(e) => {
+            // Headless Linux usually has no Secret Service running
+            eprintln!("OS keychain unavailable, using encrypted file: {}", e);
+            Box::new(EncryptedFileStore::open(app_data_dir.join(ENCRYPTED_STORAGE_FILE))?)
+        }
+    };
+
+    let secrets = Secrets::new(backend)?;
+    migrate_plaintext_storage(&app_data_dir, &secrets)?;
+    tracing::info!(backend = secrets.backend_name(), "secret_store_opened");
+
+    Ok(secrets)
+}
+
+// Gets the managed store, which is missing if it failed to open at startup
+pub fn store(app: &AppHandle) -> PluelyResult<State<'_, Secrets>> {
+    app.try_state::<Secrets>()
+        .ok_or_else(|| PluelyError::storage("Secure storage is not available"))
+}
+
+fn secret_key(namespace: &str, name: &str) -> PluelyResult<String> {
+    validate_segment("namespace", namespace)?;
+    validate_segment("name", name)?;
+    Ok(format!("{}/{}", namespace, name))
+}
+
+fn validate_segment(what: &str, value: &str) -> PluelyResult<()> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_NAME_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(PluelyError::storage(format!("Invalid secret {}: {}", what, value)))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn get_app_data_dir(app: &AppHandle) -> PluelyResult<PathBuf> {
+    let app_data_dir = app.path().app_data_dir()
+        .map_err(|e| PluelyError::storage(format!("Failed to get app data directory: {}", e)))?;
+
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| PluelyError::storage(format!("Failed to create app data directory: {}", e)))?;
+
+    Ok(app_data_dir)
+}
+
+// Moves secrets out of the old plaintext `secure_storage.json` and deletes it
+fn migrate_plaintext_storage(app_data_dir: &Path, secrets: &Secrets) -> PluelyResult<()> {
+    let legacy_path = app_data_dir.join(LEGACY_STORAGE_FILE);
+    if !legacy_path.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&legacy_path)
+        .map_err(|e| PluelyError::storage(format!("Failed to read storage file: {}", e)))?;
+    // An unreadable legacy file has nothing worth keeping
+    let fields: HashMap<String, Option<String>> = serde_json::from_str(&content).unwrap_or_default();
+
+    // Field names of the old SecureStorage struct
+    for (field, name) in [
+        ("license_key", LICENSE_KEY),
+        ("instance_id", INSTANCE_ID),
+        ("selected_pluely_model", SELECTED_PLUELY_MODEL),
+    ] {
+        if let Some(Some(value)) = fields.get(field) {
+            secrets.set(PLUELY, name, value)?;
+        }
+    }
+
+    fs::remove_file(&legacy_path)
+        .map_err(|e| PluelyError::storage(format!("Failed to remove plaintext storage file: {}", e)))?;
+    tracing::info!(backend = secrets.backend_name(), "migrated_plaintext_secure_storage");
+
+    Ok(())
+}
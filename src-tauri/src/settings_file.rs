@@ -0,0 +1,63 @@
+// Pluely settings files in the app config dir, shared by every settings store
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+use crate::atomic_file;
+use crate::error::{PluelyError, PluelyResult};
+
+/// Path of `file_name` in the app config dir, which is created when missing.
+pub fn path(app: &AppHandle, file_name: &str) -> PluelyResult<PathBuf> {
+    let app_config_dir = app.path().app_config_dir()
+        .map_err(|e| PluelyError::storage(format!("Failed to get app config directory: {}", e)))?;
+
+    fs::create_dir_all(&app_config_dir)
+        .map_err(|e| PluelyError::storage(format!("Failed to create app config directory: {}", e)))?;
+
+    Ok(app_config_dir.join(file_name))
+}
+
+fn read<T: DeserializeOwned>(app: &AppHandle, file_name: &str) -> PluelyResult<Option<T>> {
+    let path = path(app, file_name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| PluelyError::storage(format!("Failed to read {}: {}", file_name, e)))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| PluelyError::config(format!("Failed to parse {}: {}", file_name, e)))
+}
+
+/// Loads JSON settings at startup. A missing file gives the defaults, an unreadable
+/// or invalid one too so the app still starts, the error is logged.
+pub fn load_or_default<T: DeserializeOwned + Default>(
+    app: &AppHandle,
+    file_name: &str,
+    validate: impl Fn(&T) -> PluelyResult<()>,
+) -> T {
+    let loaded = read::<T>(app, file_name).and_then(|settings| {
+        if let Some(settings) = &settings {
+            validate(settings)?;
+        }
+        Ok(settings)
+    });
+
+    match loaded {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Invalid {}, using defaults: {}", file_name, e);
+            T::default()
+        }
+    }
+}
+
+/// Writes JSON settings, crash-safe.
+pub fn save<T: Serialize>(app: &AppHandle, file_name: &str, settings: &T) -> PluelyResult<()> {
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| PluelyError::storage(format!("Failed to serialize {}: {}", file_name, e)))?;
+    atomic_file::write(&path(app, file_name)?, content.as_bytes())
+}
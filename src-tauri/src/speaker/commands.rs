@@ -1,92 +1,473 @@
-// Pluely AI Speech Detection, and capture system audio (speaker output) as a stream of f32 samples.
+// Pluely AI Speech Detection, and capture system audio (speaker output) and the microphone as streams of f32 samples.
 use tauri::{AppHandle, Emitter, Manager};
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use tauri_plugin_shell::ShellExt;
-use crate::speaker::{SpeakerInput};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::speaker::{CaptureEvent, CaptureEvents, CaptureTarget, CaptureTargets, SpeakerInput, SpeakerStream};
+use crate::speaker::mic::{self, AudioDevice, MicInput, MicStream};
+use crate::speaker::echo::EchoGate;
+use crate::speaker::frames::{BufferStats, CaptureStats};
+use crate::speaker::resample::{ResampledStream, TARGET_SAMPLE_RATE};
+use crate::speaker::transcript::IncrementalTranscriber;
+use crate::speaker::settings::{VadConfig, VadSettings};
+use crate::speaker::vad::{process_chunk, to_dbfs, vad_for, VadKind};
+use crate::error::{PluelyError, PluelyResult};
+use crate::api::SttEngine;
 use anyhow::Result;
+use serde::Serialize;
 use hound::{WavSpec, WavWriter};
 use std::io::Cursor;
 use base64::{Engine as _, engine::general_purpose::STANDARD as B64};
 use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-// Pluely AI Speech Detection
-const HOP_SIZE: usize = 1024;  // Analysis chunk size (~23ms at 44.1kHz, ~21ms at 48kHz)
-const VAD_SENSITIVITY_RMS: f32 = 0.004;  // RMS sensitivity for VAD
-const SPEECH_PEAK_THRESHOLD: f32 = 0.01;  // Peak threshold for VAD
-const SILENCE_CHUNKS: usize = 47;  // ~1s silence to end speech
-const MIN_SPEECH_CHUNKS: usize = 15;  // ~0.32s min speech duration
-const PRE_SPEECH_CHUNKS: usize = 15;  // ~0.32s pre-speech buffer
+// Where a pipeline's audio comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioSource {
+    Speaker,
+    Microphone,
+}
+
+// Side of the conversation, the microphone hears us and system audio the other party
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Local,
+    Remote,
+}
+
+// Pipeline event payload labelled with its source
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceEvent<T> {
+    source: AudioSource,
+    channel: Channel,
+    #[serde(flatten)]
+    payload: T,
+}
+
+impl AudioSource {
+    pub fn channel(self) -> Channel {
+        match self {
+            AudioSource::Speaker => Channel::Remote,
+            AudioSource::Microphone => Channel::Local,
+        }
+    }
+
+    pub(super) fn emit<T: Serialize + Clone>(self, app: &AppHandle, event: &str, payload: T) {
+        let payload = SourceEvent { source: self, channel: self.channel(), payload };
+        let _ = app.emit(event, payload).map_err(|e| eprintln!("emit {} failed: {}", event, e));
+    }
+}
+
+// Payload of `speech-start`
+#[derive(Debug, Clone, Serialize)]
+struct SpeechStart {
+    segment_id: u64,
+}
+
+// Payload of `speech-detected`, the segment as a base64 WAV
+#[derive(Debug, Clone, Serialize)]
+struct SpeechSegment {
+    segment_id: u64,
+    // Offsets from the start of the capture
+    start_ms: u64,
+    end_ms: u64,
+    audio: String,
+}
+
+// Level meter updates, often enough for a smooth meter without flooding the webview
+const LEVEL_INTERVAL_MS: u64 = 100;
+
+// Payload of `audio-level`, aggregated over one interval
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioLevel {
+    rms: f32,
+    peak: f32,
+    dbfs: f32,
+    is_speech: bool,
+}
+
+// Accumulates chunk levels and hands out one reading per interval
+struct LevelMeter {
+    interval_samples: usize,
+    samples: usize,
+    sum_squares: f32,
+    peak: f32,
+    is_speech: bool,
+}
+
+impl LevelMeter {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            interval_samples: (sample_rate as u64 * LEVEL_INTERVAL_MS / 1000) as usize,
+            samples: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            is_speech: false,
+        }
+    }
+
+    fn push(&mut self, chunk: &[f32], is_speech: bool) -> Option<AudioLevel> {
+        let (rms, peak) = process_chunk(chunk);
+        self.samples += chunk.len();
+        self.sum_squares += rms * rms * chunk.len() as f32;
+        self.peak = self.peak.max(peak);
+        self.is_speech |= is_speech;
+
+        if self.samples < self.interval_samples {
+            return None;
+        }
+
+        let rms = (self.sum_squares / self.samples.max(1) as f32).sqrt();
+        let level = AudioLevel {
+            rms,
+            peak: self.peak,
+            dbfs: to_dbfs(rms),
+            is_speech: self.is_speech,
+        };
+        self.samples = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.is_speech = false;
+        Some(level)
+    }
+}
+
+// Target picked with `set_capture_target`, used by the next system audio capture
+#[derive(Default)]
+pub struct SelectedCaptureTarget(Mutex<CaptureTarget>);
+
+impl SelectedCaptureTarget {
+    fn get(&self) -> CaptureTarget {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// Buffer counters of the latest system audio and microphone captures, kept after they stop
+#[derive(Default)]
+pub struct CaptureStatsState {
+    speaker: Mutex<Option<Arc<BufferStats>>>,
+    microphone: Mutex<Option<Arc<BufferStats>>>,
+}
+
+impl CaptureStatsState {
+    fn slot(&self, source: AudioSource) -> &Mutex<Option<Arc<BufferStats>>> {
+        match source {
+            AudioSource::Speaker => &self.speaker,
+            AudioSource::Microphone => &self.microphone,
+        }
+    }
+
+    fn get(&self, source: AudioSource) -> Option<CaptureStats> {
+        self.slot(source).lock().unwrap().as_ref().map(|stats| stats.snapshot())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureStatsReport {
+    speaker: Option<CaptureStats>,
+    microphone: Option<CaptureStats>,
+}
+
+// Options shared by the speaker and microphone captures
+struct CaptureOptions {
+    partial_interval_ms: Option<u64>,
+    engine: Option<SttEngine>,
+    vad: Option<VadKind>,
+    vad_config: Option<VadConfig>,
+    conversation: Option<Conversation>,
+}
+
+// Clock and echo gate shared by the two pipelines of a conversation capture
+#[derive(Clone)]
+struct Conversation {
+    started: Instant,
+    echo: Option<Arc<EchoGate>>,
+}
 
+// `vad_config` overrides the saved segmentation settings for this capture only and isn't persisted.
+// Without it the capture follows the saved settings, including `update_vad_config` calls while it runs.
+// Incremental mode (`partial_interval_ms` set) transcribes segments here and emits
+// `transcript-partial`/`transcript-final` instead of `speech-detected`.
+// `audio-level` reports the input level every 100ms so the UI can show a meter.
+// Every event carries `source: "speaker"`.
+// Fails with an `audio` error when the capture can't be opened; if it dies later,
+// `capture-stopped` reports why.
 #[tauri::command]
-pub async fn start_system_audio_capture(app: AppHandle) -> Result<(), String> {
+pub async fn start_system_audio_capture(
+    app: AppHandle,
+    partial_interval_ms: Option<u64>,
+    engine: Option<SttEngine>,
+    vad: Option<VadKind>,
+    vad_config: Option<VadConfig>,
+) -> PluelyResult<()> {
     let state = app.state::<crate::AudioState>();
-    let mut guard = state.stream_task.lock().unwrap();
+    if state.stream_task.lock().unwrap().is_some() {
+        return Err(PluelyError::audio("Capture already running"));
+    }
+
+    let target = app.state::<SelectedCaptureTarget>().get();
+    let mut stream = open_capture(move || open_speaker(target)).await?;
 
+    // Checked again, another start may have won while the device was opening
+    let mut guard = state.stream_task.lock().unwrap();
     if guard.is_some() {
-        return Err("Capture already running".to_string());
+        return Err(PluelyError::audio("Capture already running"));
     }
+    let sr = stream.sample_rate();
+    let stats = stream.stats();
+    if let Some(events) = stream.take_events() {
+        forward_capture_events(&app, AudioSource::Speaker, events);
+    }
+
+    let options = CaptureOptions { partial_interval_ms, engine, vad, vad_config, conversation: None };
+    *guard = Some(spawn_pipeline(&app, AudioSource::Speaker, stream, sr, stats, options)?);
+    Ok(())
+}
 
-    let input = SpeakerInput::new().map_err(|e| e.to_string())?;
-    let mut stream = input.stream();
+// Same pipeline and events as `start_system_audio_capture`, labelled `source: "microphone"`.
+// `device` is a name from `get_input_devices`, the default input device when omitted.
+#[tauri::command]
+pub async fn start_microphone_capture(
+    app: AppHandle,
+    device: Option<String>,
+    partial_interval_ms: Option<u64>,
+    engine: Option<SttEngine>,
+    vad: Option<VadKind>,
+    vad_config: Option<VadConfig>,
+) -> PluelyResult<()> {
+    let state = app.state::<crate::AudioState>();
+    if state.mic_task.lock().unwrap().is_some() {
+        return Err(PluelyError::audio("Microphone capture already running"));
+    }
+
+    let stream = open_capture(move || open_microphone(device.as_deref())).await?;
+
+    // Checked again, another start may have won while the device was opening
+    let mut guard = state.mic_task.lock().unwrap();
+    if guard.is_some() {
+        return Err(PluelyError::audio("Microphone capture already running"));
+    }
     let sr = stream.sample_rate();
+    let stats = stream.stats();
+
+    let options = CaptureOptions { partial_interval_ms, engine, vad, vad_config, conversation: None };
+    *guard = Some(spawn_pipeline(&app, AudioSource::Microphone, stream, sr, stats, options)?);
+    Ok(())
+}
+
+// Captures both sides of a call at once, the microphone as `channel: "local"` and system audio
+// as `channel: "remote"`, with timestamps from the same clock so the segments interleave.
+// `echo_suppression` drops microphone speech that is the remote side playing through the speakers.
+#[tauri::command]
+pub async fn start_conversation_capture(
+    app: AppHandle,
+    device: Option<String>,
+    partial_interval_ms: Option<u64>,
+    engine: Option<SttEngine>,
+    vad: Option<VadKind>,
+    vad_config: Option<VadConfig>,
+    echo_suppression: Option<bool>,
+) -> PluelyResult<()> {
+    let state = app.state::<crate::AudioState>();
+    if state.stream_task.lock().unwrap().is_some() || state.mic_task.lock().unwrap().is_some() {
+        return Err(PluelyError::audio("Capture already running"));
+    }
+
+    let target = app.state::<SelectedCaptureTarget>().get();
+    let (mut speaker_stream, mic_stream) = open_capture(move || {
+        Ok((open_speaker(target)?, open_microphone(device.as_deref())?))
+    })
+    .await?;
+
+    // Checked again, another start may have won while the devices were opening
+    let mut speaker_guard = state.stream_task.lock().unwrap();
+    let mut mic_guard = state.mic_task.lock().unwrap();
+    if speaker_guard.is_some() || mic_guard.is_some() {
+        return Err(PluelyError::audio("Capture already running"));
+    }
+
+    let speaker_sr = speaker_stream.sample_rate();
+    let speaker_stats = speaker_stream.stats();
+    if let Some(events) = speaker_stream.take_events() {
+        forward_capture_events(&app, AudioSource::Speaker, events);
+    }
+    let mic_sr = mic_stream.sample_rate();
+    let mic_stats = mic_stream.stats();
+
+    let conversation = Conversation {
+        started: Instant::now(),
+        echo: echo_suppression.unwrap_or(false).then(|| Arc::new(EchoGate::default())),
+    };
+    let speaker_options = CaptureOptions {
+        partial_interval_ms,
+        engine,
+        vad,
+        vad_config: vad_config.clone(),
+        conversation: Some(conversation.clone()),
+    };
+    let mic_options = CaptureOptions {
+        partial_interval_ms,
+        engine,
+        vad,
+        vad_config,
+        conversation: Some(conversation),
+    };
+
+    let speaker_task = spawn_pipeline(&app, AudioSource::Speaker, speaker_stream, speaker_sr, speaker_stats, speaker_options)?;
+    *mic_guard = Some(spawn_pipeline(&app, AudioSource::Microphone, mic_stream, mic_sr, mic_stats, mic_options)?);
+    *speaker_guard = Some(speaker_task);
+    Ok(())
+}
+
+// Opening a device blocks until its capture thread starts, so it runs off the async runtime
+async fn open_capture<T, F>(open: F) -> PluelyResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> PluelyResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(open)
+        .await
+        .map_err(|e| PluelyError::audio(format!("Capture startup task failed: {}", e)))?
+}
+
+fn open_speaker(target: CaptureTarget) -> PluelyResult<SpeakerStream> {
+    let input = SpeakerInput::with_target(target).map_err(|e| PluelyError::audio(e.to_string()))?;
+    Ok(input.stream()?)
+}
+
+fn open_microphone(device: Option<&str>) -> PluelyResult<MicStream> {
+    MicInput::new(device)
+        .and_then(MicInput::stream)
+        .map_err(|e| PluelyError::audio(e.to_string()))
+}
+
+// Segments a capture stream into speech on its own task
+fn spawn_pipeline<S>(
+    app: &AppHandle,
+    source: AudioSource,
+    stream: S,
+    sample_rate: u32,
+    stats: Arc<BufferStats>,
+    options: CaptureOptions,
+) -> PluelyResult<JoinHandle<()>>
+where
+    S: Stream<Item = Vec<f32>> + Unpin + Send + 'static,
+{
+    // An override is pinned for this capture, its sender is dropped right away so it never changes
+    let mut config_rx = match options.vad_config {
+        Some(vad_config) => {
+            vad_config.validate()?;
+            watch::channel(vad_config).1
+        }
+        None => app.state::<VadSettings>().subscribe(),
+    };
+
+    *app.state::<CaptureStatsState>().slot(source).lock().unwrap() = Some(stats);
+
+    // VAD, transcription and uploads all run at 16 kHz whatever the device rate
+    let mut stream = ResampledStream::new(stream, sample_rate);
+    let sr = TARGET_SAMPLE_RATE;
+
+
+    let mut vad = vad_for(options.vad.unwrap_or_default(), sr);
+    let engine = options.engine.unwrap_or_default();
+    let mut transcriber = options.partial_interval_ms
+        .map(|interval_ms| IncrementalTranscriber::new(app.clone(), source, engine, sr, interval_ms));
+
+    // Pipelines of a conversation count from its start rather than their own
+    let (offset_ms, echo) = match options.conversation {
+        Some(conversation) => (conversation.started.elapsed().as_millis() as u64, conversation.echo),
+        None => (0, None),
+    };
 
     let app_clone = app.clone();
     let task = tokio::spawn(async move {
-        let mut buffer: VecDeque<f32> = VecDeque::new();  // Raw f32 from stream
+        let mut buffer: VecDeque<f32> = VecDeque::new();  // Frames from the stream, split into hops below
         let mut pre_speech: VecDeque<f32> = VecDeque::new();  // Pre-speech buffer
         let mut speech_buffer = Vec::new();  // Collected speech
         let mut in_speech = false;
         let mut silence_chunks = 0;
         let mut speech_chunks = 0;
-        let max_samples = sr as usize * 30;  // Safety cap: 30s
+        let mut params = config_rx.borrow_and_update().params(sr);
+        let mut meter = LevelMeter::new(sr);
+        let mut total_samples: u64 = offset_ms * sr as u64 / 1000;  // Samples analysed since capture start
+        let mut segment_id: u64 = 0;
+        let mut segment_start: u64 = 0;  // First sample of the segment, pre-speech included
 
-        while let Some(sample) = stream.next().await {
-            buffer.push_back(sample);
+        while let Some(frame) = stream.next().await {
+            buffer.extend(frame);
+
+            // Settings changed by update_vad_config apply from the next chunk
+            if config_rx.has_changed().unwrap_or(false) {
+                params = config_rx.borrow_and_update().params(sr);
+            }
 
             // Process in chunks
-            while buffer.len() >= HOP_SIZE {
-                let mut mono = Vec::with_capacity(HOP_SIZE);
-                for _ in 0..HOP_SIZE {
+            while buffer.len() >= params.hop_size {
+                let mut mono = Vec::with_capacity(params.hop_size);
+                for _ in 0..params.hop_size {
                     if let Some(v) = buffer.pop_front() {
                         mono.push(v);
                     }
                 }
+                let chunk_start = total_samples;
+                total_samples += params.hop_size as u64;
 
-                let (rms, peak) = process_chunk(&mono);
-                    let is_speech = rms > VAD_SENSITIVITY_RMS || peak > SPEECH_PEAK_THRESHOLD;
+                    let mut is_speech = vad.is_speech(&mono);
+                    if let Some(echo) = echo.as_deref() {
+                        let at_ms = chunk_start * 1000 / sr.max(1) as u64;
+                        let (rms, _) = process_chunk(&mono);
+                        match source {
+                            AudioSource::Speaker => echo.push_remote(at_ms, rms, is_speech),
+                            AudioSource::Microphone => is_speech &= !echo.is_echo(at_ms, rms),
+                        }
+                    }
+                    if let Some(level) = meter.push(&mono, is_speech) {
+                        source.emit(&app_clone, "audio-level", level);
+                    }
+                    if let Some(levels) = vad.take_calibration() {
+                        source.emit(&app_clone, "vad-calibrated", levels);
+                    }
 
                     if is_speech {
                         if !in_speech {
                             in_speech = true;
                             speech_chunks = 0;
                             silence_chunks = 0;
+                            segment_id += 1;
+                            segment_start = chunk_start - pre_speech.len() as u64;
                             speech_buffer.extend(pre_speech.drain(..));  // Prepend pre-speech
-                            let _ = app_clone.emit("speech-start", ()).map_err(|e| eprintln!("emit speech-start failed: {}", e));
+                            source.emit(&app_clone, "speech-start", SpeechStart { segment_id });
                         }
                         speech_chunks += 1;
                         speech_buffer.extend_from_slice(&mono);
-                        if speech_buffer.len() > max_samples {
+                        if speech_buffer.len() > params.max_samples {
                             // Force emit
-                            if let Ok(b64) = samples_to_wav_b64(sr, &speech_buffer) {
-                                let _ = app_clone.emit("speech-detected", b64).map_err(|e| eprintln!("emit speech-detected failed: {}", e));
-                            }
+                            emit_segment(&app_clone, source, transcriber.as_mut(), sr, segment_id, segment_start, &speech_buffer);
                             speech_buffer.clear();
                             in_speech = false;
+                        } else if speech_chunks >= params.min_speech_chunks {
+                            // Long enough to be emitted, so every partial gets a final transcript
+                            if let Some(transcriber) = transcriber.as_mut() {
+                                transcriber.partial(segment_id, segment_start, &speech_buffer);
+                            }
                         }
                     } else {
                         if in_speech {
                             silence_chunks += 1;
                             speech_buffer.extend_from_slice(&mono);
-                            if silence_chunks >= SILENCE_CHUNKS {
-                                if speech_chunks >= MIN_SPEECH_CHUNKS && !speech_buffer.is_empty() {
+                            if silence_chunks >= params.silence_chunks {
+                                if speech_chunks >= params.min_speech_chunks && !speech_buffer.is_empty() {
                                     // Trim trailing silence
-                                    let trim = (SILENCE_CHUNKS / 2) * HOP_SIZE;
+                                    let trim = (params.silence_chunks / 2) * params.hop_size;
                                     if speech_buffer.len() > trim {
                                         speech_buffer.truncate(speech_buffer.len() - trim);
                                     }
-                                    if let Ok(b64) = samples_to_wav_b64(sr, &speech_buffer) {
-                                        let _ = app_clone.emit("speech-detected", b64).map_err(|e| eprintln!("emit speech-detected failed: {}", e));
-                                    }
+                                    emit_segment(&app_clone, source, transcriber.as_mut(), sr, segment_id, segment_start, &speech_buffer);
                                 }
                                 speech_buffer.clear();
                                 in_speech = false;
@@ -95,8 +476,8 @@ pub async fn start_system_audio_capture(app: AppHandle) -> Result<(), String> {
                             }
                         } else {
                             // Not in speech: maintain pre-speech buffer
-                            pre_speech.extend(mono.into_iter());
-                            while pre_speech.len() > PRE_SPEECH_CHUNKS * HOP_SIZE {
+                            pre_speech.extend(mono);
+                            while pre_speech.len() > params.pre_speech_chunks * params.hop_size {
                                 pre_speech.pop_front();
                             }
                         }
@@ -105,25 +486,53 @@ pub async fn start_system_audio_capture(app: AppHandle) -> Result<(), String> {
         }
     });
 
-    *guard = Some(task);
-    Ok(())
+    Ok(task)
 }
 
-// Process a chunk for Pluely AI Speech Detection (RMS and peak calculation)
-fn process_chunk(mono_chunk: &[f32]) -> (f32, f32) {
-    let mut sumsq = 0.0f32;
-    let mut peak = 0.0f32;
-    for &v in mono_chunk {
-        let a = v.abs();
-        peak = peak.max(a);
-        sumsq += v * v;
+// Reports device switches and failures of a running capture as `audio-device-changed`
+// and `audio-capture-error`, and the capture thread dying as `capture-stopped`
+fn forward_capture_events(app: &AppHandle, source: AudioSource, mut events: CaptureEvents) {
+    let app = app.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            match event {
+                CaptureEvent::DeviceChanged(change) => source.emit(&app, "audio-device-changed", change),
+                CaptureEvent::Error(error) => source.emit(&app, "audio-capture-error", error),
+                CaptureEvent::Stopped(stopped) => source.emit(&app, "capture-stopped", stopped),
+            }
+        }
+    });
+}
+
+// Hands a finished segment to the incremental transcriber, or to the frontend as a WAV
+fn emit_segment(
+    app: &AppHandle,
+    source: AudioSource,
+    transcriber: Option<&mut IncrementalTranscriber>,
+    sample_rate: u32,
+    segment_id: u64,
+    segment_start: u64,
+    samples: &[f32],
+) {
+    match transcriber {
+        Some(transcriber) => transcriber.finish(segment_id, segment_start, samples),
+        None => {
+            if let Ok(audio) = samples_to_wav_b64(sample_rate, samples) {
+                let to_ms = |samples: u64| samples * 1000 / sample_rate.max(1) as u64;
+                let segment = SpeechSegment {
+                    segment_id,
+                    start_ms: to_ms(segment_start),
+                    end_ms: to_ms(segment_start + samples.len() as u64),
+                    audio,
+                };
+                source.emit(app, "speech-detected", segment);
+            }
+        }
     }
-    let rms = (sumsq / mono_chunk.len() as f32).sqrt();
-    (rms, peak)
 }
 
 // Send samples to Pluely AI Speech
-fn samples_to_wav_b64(sample_rate: u32, mono_f32: &[f32]) -> Result<String, String> {
+pub(super) fn samples_to_wav_b64(sample_rate: u32, mono_f32: &[f32]) -> Result<String, String> {
     let mut cursor = Cursor::new(Vec::new());
     let spec = WavSpec {
         channels: 1,
@@ -144,7 +553,17 @@ fn samples_to_wav_b64(sample_rate: u32, mono_f32: &[f32]) -> Result<String, Stri
 }
 
 #[tauri::command]
-pub async fn stop_system_audio_capture(app: AppHandle) -> Result<(), String> {
+pub fn get_vad_config(app: AppHandle) -> VadConfig {
+    app.state::<VadSettings>().get()
+}
+
+#[tauri::command]
+pub fn update_vad_config(app: AppHandle, config: VadConfig) -> PluelyResult<()> {
+    app.state::<VadSettings>().update(&app, config)
+}
+
+#[tauri::command]
+pub async fn stop_system_audio_capture(app: AppHandle) -> PluelyResult<()> {
     let state = app.state::<crate::AudioState>();
     let mut guard = state.stream_task.lock().unwrap();
 
@@ -155,20 +574,74 @@ pub async fn stop_system_audio_capture(app: AppHandle) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn check_system_audio_access(_app: AppHandle) -> Result<bool, String> {
-    let mut stream = SpeakerInput::new().map_err(|e| e.to_string())?.stream();
+pub async fn stop_microphone_capture(app: AppHandle) -> PluelyResult<()> {
+    let state = app.state::<crate::AudioState>();
+    let mut guard = state.mic_task.lock().unwrap();
+
+    if let Some(task) = guard.take() {
+        task.abort();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_conversation_capture(app: AppHandle) -> PluelyResult<()> {
+    stop_system_audio_capture(app.clone()).await?;
+    stop_microphone_capture(app).await
+}
+
+#[tauri::command]
+pub fn get_input_devices() -> PluelyResult<Vec<AudioDevice>> {
+    mic::list_input_devices().map_err(|e| PluelyError::audio(e.to_string()))
+}
+
+// Monitor sources and application streams, Linux only
+#[tauri::command]
+pub fn get_capture_targets() -> PluelyResult<CaptureTargets> {
+    crate::speaker::list_capture_targets()
+}
+
+// Overruns count the times a capture buffer was full and new audio was dropped,
+// i.e. the pipeline fell behind. None for a source that hasn't been captured yet.
+#[tauri::command]
+pub fn get_capture_stats(app: AppHandle) -> CaptureStatsReport {
+    let state = app.state::<CaptureStatsState>();
+    CaptureStatsReport {
+        speaker: state.get(AudioSource::Speaker),
+        microphone: state.get(AudioSource::Microphone),
+    }
+}
+
+// Applies to captures started afterwards, a running capture keeps its target
+#[tauri::command]
+pub fn set_capture_target(app: AppHandle, target: CaptureTarget) -> PluelyResult<()> {
+    if !cfg!(target_os = "linux") && target != CaptureTarget::default() {
+        return Err(PluelyError::unsupported("Capture targets are only supported on Linux"));
+    }
+    *app.state::<SelectedCaptureTarget>().0.lock().unwrap() = target;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn check_system_audio_access(_app: AppHandle) -> PluelyResult<bool> {
+    let mut stream = open_capture(|| open_speaker(CaptureTarget::default())).await?;
     Ok(stream.next().await.is_some())
 }
 
 #[tauri::command]
-pub async fn request_system_audio_access(app: AppHandle) -> Result<(), String> {
+pub async fn request_system_audio_access(app: AppHandle) -> PluelyResult<()> {
     #[cfg(target_os = "macos")]
     {
-        app.shell().command("open").args(["x-apple.systempreferences:com.apple.preference.security?Privacy_AudioCapture"]).spawn().map_err(|e| e.to_string())?;
+        app.shell().command("open").args(["x-apple.systempreferences:com.apple.preference.security?Privacy_AudioCapture"]).spawn()
+            .map_err(|e| PluelyError::audio(format!("Failed to open the privacy settings: {}", e)))?;
     }
     #[cfg(target_os = "windows")]
     {
-        app.shell().command("ms-settings:sound").spawn().map_err(|e| e.to_string())?;
+        app.shell().command("ms-settings:sound").spawn()
+            .map_err(|e| PluelyError::audio(format!("Failed to open the sound settings: {}", e)))?;
     }
+    // Nothing to open elsewhere
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let _ = app;
     Ok(())
 }
\ No newline at end of file
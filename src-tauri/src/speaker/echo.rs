@@ -0,0 +1,103 @@
+// Pluely echo suppression, tells the other party leaking from the speakers into the microphone
+// apart from the local speaker by comparing the energy envelopes of both captures
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+const REMOTE_HISTORY_MS: u64 = 2000;  // Remote envelope kept for comparison
+const LOCAL_WINDOW: usize = 40;  // ~0.9s of microphone chunks compared at once
+const MIN_LOCAL_CHUNKS: usize = 16;  // Too little envelope to correlate below this
+const MAX_ECHO_DELAY_MS: u64 = 300;  // Playback and acoustic delay searched
+const DELAY_STEP_MS: u64 = 10;
+const MAX_CHUNK_MS: u64 = 100;  // Longest analysis chunk, hop_ms is at most 100
+const REMOTE_ACTIVE_MS: u64 = 500;  // Echo only while the remote side spoke this recently
+const ECHO_CORRELATION: f32 = 0.8;  // Envelope correlation above this is echo, double talk stays below
+
+// Shared by the two pipelines of a conversation capture, timestamps are on the shared clock
+#[derive(Default)]
+pub struct EchoGate {
+    state: Mutex<EchoState>,
+}
+
+#[derive(Default)]
+struct EchoState {
+    // (chunk start ms, ln energy) of the system audio
+    remote: VecDeque<(u64, f32)>,
+    last_remote_speech_ms: Option<u64>,
+    // Same for the microphone
+    local: VecDeque<(u64, f32)>,
+}
+
+impl EchoGate {
+    /// Records a system audio chunk.
+    pub fn push_remote(&self, at_ms: u64, rms: f32, is_speech: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.remote.push_back((at_ms, log_energy(rms)));
+        while state.remote.front().is_some_and(|&(t, _)| t + REMOTE_HISTORY_MS < at_ms) {
+            state.remote.pop_front();
+        }
+        if is_speech {
+            state.last_remote_speech_ms = Some(at_ms);
+        }
+    }
+
+    /// Records a microphone chunk and tells whether it is the remote side's echo.
+    pub fn is_echo(&self, at_ms: u64, rms: f32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.local.len() == LOCAL_WINDOW {
+            state.local.pop_front();
+        }
+        state.local.push_back((at_ms, log_energy(rms)));
+
+        let remote_active = state
+            .last_remote_speech_ms
+            .is_some_and(|t| at_ms.saturating_sub(t) <= REMOTE_ACTIVE_MS);
+        if !remote_active || state.local.len() < MIN_LOCAL_CHUNKS {
+            return false;
+        }
+
+        (0..=MAX_ECHO_DELAY_MS)
+            .step_by(DELAY_STEP_MS as usize)
+            .filter_map(|delay| state.correlation(delay))
+            .any(|correlation| correlation >= ECHO_CORRELATION)
+    }
+}
+
+impl EchoState {
+    // Pearson correlation of the local envelope with the remote one `delay_ms` earlier
+    fn correlation(&self, delay_ms: u64) -> Option<f32> {
+        let pairs: Vec<(f32, f32)> = self
+            .local
+            .iter()
+            .filter_map(|&(t, local)| Some((local, self.remote_at(t.checked_sub(delay_ms)?)?)))
+            .collect();
+        if pairs.len() < MIN_LOCAL_CHUNKS {
+            return None;
+        }
+
+        let n = pairs.len() as f32;
+        let (mean_l, mean_r) = pairs
+            .iter()
+            .fold((0.0, 0.0), |(l, r), &(a, b)| (l + a / n, r + b / n));
+        let (mut cov, mut var_l, mut var_r) = (0.0f32, 0.0f32, 0.0f32);
+        for &(l, r) in &pairs {
+            cov += (l - mean_l) * (r - mean_r);
+            var_l += (l - mean_l).powi(2);
+            var_r += (r - mean_r).powi(2);
+        }
+        if var_l <= f32::EPSILON || var_r <= f32::EPSILON {
+            return None;
+        }
+        Some(cov / (var_l * var_r).sqrt())
+    }
+
+    // Remote chunk covering `at_ms`, chunks are ~hop_ms long on both sides
+    fn remote_at(&self, at_ms: u64) -> Option<f32> {
+        let index = self.remote.partition_point(|&(t, _)| t <= at_ms);
+        let &(t, energy) = self.remote.get(index.checked_sub(1)?)?;
+        (at_ms - t <= MAX_CHUNK_MS).then_some(energy)
+    }
+}
+
+fn log_energy(rms: f32) -> f32 {
+    (rms * rms + 1e-10).ln()
+}
@@ -0,0 +1,194 @@
+// Pluely capture buffering, a bounded ring buffer between a capture thread and its stream
+// that hands out fixed-size frames and counts the audio it had to drop
+use futures_util::Stream;
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+const FRAME_MS: u32 = 20;  // Audio per frame handed to the pipeline
+const BUFFER_MS: u32 = 2000;  // Audio kept while the pipeline is busy, newer samples are dropped beyond it
+
+// Counters of one capture's buffer, shared with `get_capture_stats`
+#[derive(Debug)]
+pub struct BufferStats {
+    frame_len: usize,
+    capacity: usize,
+    buffered: AtomicUsize,
+    frames: AtomicU64,
+    overruns: AtomicU64,
+    dropped_samples: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureStats {
+    frame_len: usize,
+    capacity: usize,
+    buffered_samples: usize,
+    frames: u64,
+    // Writes that found the buffer full, their samples that didn't fit are in dropped_samples
+    overruns: u64,
+    dropped_samples: u64,
+}
+
+impl BufferStats {
+    pub fn snapshot(&self) -> CaptureStats {
+        CaptureStats {
+            frame_len: self.frame_len,
+            capacity: self.capacity,
+            buffered_samples: self.buffered.load(Ordering::Relaxed),
+            frames: self.frames.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            dropped_samples: self.dropped_samples.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct WakerState {
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<WakerState>,
+    stats: Arc<BufferStats>,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, WakerState> {
+        // A capture thread that panicked mid-push must still be able to close the stream
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn close(&self) {
+        let waker = {
+            let mut state = self.lock();
+            state.closed = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates the buffer of a capture running at `sample_rate`.
+pub fn frame_channel(sample_rate: u32) -> (FrameSender, FrameReceiver) {
+    let frame_len = (sample_rate.max(1000) * FRAME_MS / 1000) as usize;
+    let capacity = (sample_rate.max(1000) * BUFFER_MS / 1000) as usize;
+    let (producer, consumer) = HeapRb::<f32>::new(capacity).split();
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(WakerState { waker: None, closed: false }),
+        stats: Arc::new(BufferStats {
+            frame_len,
+            capacity,
+            buffered: AtomicUsize::new(0),
+            frames: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            dropped_samples: AtomicU64::new(0),
+        }),
+    });
+
+    let sender = FrameSender { producer, shared: shared.clone() };
+    let receiver = FrameReceiver { consumer, shared };
+    (sender, receiver)
+}
+
+// Capture thread side, closes the stream when dropped
+pub struct FrameSender {
+    producer: HeapProd<f32>,
+    shared: Arc<Shared>,
+}
+
+impl FrameSender {
+    /// Queues captured samples, dropping what doesn't fit. Returns how many were queued.
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        let pushed = self.producer.push_slice(samples);
+        let stats = &self.shared.stats;
+        if pushed < samples.len() {
+            stats.overruns.fetch_add(1, Ordering::Relaxed);
+            stats.dropped_samples.fetch_add((samples.len() - pushed) as u64, Ordering::Relaxed);
+        }
+
+        let buffered = self.producer.occupied_len();
+        stats.buffered.store(buffered, Ordering::Relaxed);
+        // Only wake the stream once it has a whole frame to hand out
+        if buffered >= stats.frame_len {
+            let waker = self.shared.lock().waker.take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+        pushed
+    }
+
+    // True once the stream was dropped
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+
+    pub fn close(&self) {
+        self.shared.close();
+    }
+}
+
+impl Drop for FrameSender {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+// Stream side, yields frames of `frame_len` samples until closed and drained
+pub struct FrameReceiver {
+    consumer: HeapCons<f32>,
+    shared: Arc<Shared>,
+}
+
+impl FrameReceiver {
+    pub fn stats(&self) -> Arc<BufferStats> {
+        self.shared.stats.clone()
+    }
+
+    // Tells the capture thread to stop, frames already buffered can still be read
+    pub fn close(&self) {
+        self.shared.close();
+    }
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl Stream for FrameReceiver {
+    type Item = Vec<f32>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let stats = &this.shared.stats;
+        // Checked under the lock, so a push between the check and storing the waker still wakes us
+        let mut state = this.shared.lock();
+
+        if this.consumer.occupied_len() >= stats.frame_len {
+            drop(state);
+            let mut frame = vec![0.0; stats.frame_len];
+            this.consumer.pop_slice(&mut frame);
+            stats.frames.fetch_add(1, Ordering::Relaxed);
+            stats.buffered.store(this.consumer.occupied_len(), Ordering::Relaxed);
+            return Poll::Ready(Some(frame));
+        }
+
+        // A partial frame left at the end is dropped
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -1,181 +1,509 @@
-// Pluely linux speaker input and stream
+// Pluely linux speaker input and stream, records a PulseAudio (or pipewire-pulse) monitor source
 use anyhow::{anyhow, Result};
 use futures_util::Stream;
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-use std::task::{Poll, Waker};
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::task::Poll;
 use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
 use libpulse_binding as pulse;
-use libpulse_simple_binding as psimple;
 
-use pulse::sample::{Spec, Format};
-use pulse::stream::Direction;
-use psimple::Simple;
+use pulse::callbacks::ListResult;
+use pulse::context::subscribe::InterestMaskSet;
+use pulse::context::{Context, FlagSet as ContextFlagSet, State as ContextState};
+use pulse::def::BufferAttr;
+use pulse::mainloop::standard::{IterateResult, Mainloop};
+use pulse::operation::{Operation, State as OperationState};
+use pulse::proplist::properties;
+use pulse::sample::{Format, Spec};
+use pulse::stream::{FlagSet as StreamFlagSet, PeekResult, State as StreamState, Stream as PulseStream};
+
+use super::frames::{frame_channel, BufferStats, FrameReceiver, FrameSender};
+use super::{
+    panic_message, reopen_with_backoff, ApplicationStream, CaptureEvent, CaptureEvents, CaptureStartError,
+    CaptureTarget, CaptureTargets, MonitorSource,
+};
+
+const SAMPLE_RATE: u32 = 16000;
+const FRAGMENT_MS: u32 = 20;  // Record latency asked from the server
+const IDLE_SLEEP: Duration = Duration::from_millis(10);
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct SpeakerInput {
     server_name: Option<String>,
+    target: CaptureTarget,
 }
 
 impl SpeakerInput {
     pub fn new() -> Result<Self> {
-        Ok(Self { 
-            server_name: None 
+        Self::with_target(CaptureTarget::default())
+    }
+
+    pub fn with_target(target: CaptureTarget) -> Result<Self> {
+        Ok(Self {
+            // None lets libpulse pick the server, PULSE_SERVER included
+            server_name: None,
+            target,
         })
     }
 
-    pub fn stream(self) -> SpeakerStream {
-        let sample_queue = Arc::new(Mutex::new(VecDeque::new()));
-        let waker_state = Arc::new(Mutex::new(WakerState {
-            waker: None,
-            shutdown: false,
-        }));
-        let (init_tx, init_rx) = std::sync::mpsc::channel();
+    pub fn stream(self) -> Result<SpeakerStream, CaptureStartError> {
+        let (mut sender, frames) = frame_channel(SAMPLE_RATE);
+        let (init_tx, init_rx) = mpsc::channel();
+        let (events_tx, events_rx) = unbounded_channel();
 
-        let queue_clone = sample_queue.clone();
-        let waker_clone = waker_state.clone();
         let server_name = self.server_name;
+        let target = self.target;
 
+        // Dropping the sender at the end ends the stream when the capture gave up on its own
         let capture_thread = thread::spawn(move || {
-            if let Err(e) = SpeakerStream::capture_audio_loop(
-                queue_clone,
-                waker_clone,
-                server_name.as_deref(),
-                init_tx,
-            ) {
-                eprintln!("Audio capture loop failed: {}", e);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                SpeakerStream::capture_audio_loop(
+                    &mut sender,
+                    server_name.as_deref(),
+                    &target,
+                    init_tx,
+                    events_tx.clone(),
+                )
+            }));
+            let stopped = match result {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(panic) => Some(panic_message(&*panic)),
+            };
+            if let Some(reason) = stopped {
+                eprintln!("Audio capture loop failed: {}", reason);
+                let _ = events_tx.send(CaptureEvent::stopped(reason));
             }
         });
 
-        let sample_rate = match init_rx.recv() {
-            Ok(Ok(sr)) => sr,
-            Ok(Err(e)) => {
-                eprintln!("Audio initialization failed: {}", e);
-                0
+        let started = match init_rx.recv_timeout(INIT_TIMEOUT) {
+            Ok(started) => started,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // The thread may be blocked on an unresponsive server, so it is left to exit
+                // on its own once it sees the closed channel rather than joined here
+                frames.close();
+                return Err(CaptureStartError::Timeout);
             }
-            Err(e) => {
-                eprintln!("Failed to receive audio init signal: {}", e);
-                0
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(CaptureStartError::backend("Capture thread exited during startup"))
             }
         };
+        if let Err(e) = started {
+            let _ = capture_thread.join();
+            return Err(e);
+        }
 
-        SpeakerStream {
-            sample_queue,
-            waker_state,
+        Ok(SpeakerStream {
+            frames,
             capture_thread: Some(capture_thread),
-            sample_rate,
-        }
+            events: Some(events_rx),
+        })
     }
 }
 
-struct WakerState {
-    waker: Option<Waker>,
-    shutdown: bool,
+/// Lists the monitor sources and application streams that can be captured.
+pub fn list_capture_targets() -> Result<CaptureTargets> {
+    Connection::new(None)?.capture_targets()
 }
 
 pub struct SpeakerStream {
-    sample_queue: Arc<Mutex<VecDeque<f32>>>,
-    waker_state: Arc<Mutex<WakerState>>,
+    frames: FrameReceiver,
     capture_thread: Option<thread::JoinHandle<()>>,
-    sample_rate: u32,
+    events: Option<CaptureEvents>,
 }
 
 impl SpeakerStream {
     pub fn sample_rate(&self) -> u32 {
-        self.sample_rate
+        SAMPLE_RATE
+    }
+
+    pub fn take_events(&mut self) -> Option<CaptureEvents> {
+        self.events.take()
+    }
+
+    pub fn stats(&self) -> Arc<BufferStats> {
+        self.frames.stats()
     }
 
     fn capture_audio_loop(
-        sample_queue: Arc<Mutex<VecDeque<f32>>>,
-        waker_state: Arc<Mutex<WakerState>>,
-        _server_name: Option<&str>,
-        init_tx: std::sync::mpsc::Sender<Result<u32>>,
+        frames: &mut FrameSender,
+        server_name: Option<&str>,
+        target: &CaptureTarget,
+        init_tx: mpsc::Sender<Result<(), CaptureStartError>>,
+        events: UnboundedSender<CaptureEvent>,
     ) -> Result<()> {
+        let open = || Capture::open(server_name, target).map_err(anyhow::Error::from);
+
+        let mut capture = match Capture::open(server_name, target) {
+            Ok(capture) => {
+                let _ = init_tx.send(Ok(()));
+                capture
+            }
+            Err(e) => {
+                let _ = init_tx.send(Err(e));
+                return Ok(());
+            }
+        };
+
+        while !frames.is_closed() {
+            let read = match capture.read(frames) {
+                Ok(read) => read,
+                Err(e) => {
+                    // The audio server restarted or the recorded device went away
+                    eprintln!("PulseAudio capture failed: {}", e);
+                    let _ = events.send(CaptureEvent::error(&e, true));
+                    match reopen_with_backoff(|| frames.is_closed(), open) {
+                        Ok(reopened) => {
+                            capture = reopened;
+                            let _ = events.send(CaptureEvent::device_changed(&capture.route.source));
+                            continue;
+                        }
+                        Err(_) if frames.is_closed() => return Ok(()),
+                        Err(e) => {
+                            let _ = events.send(CaptureEvent::error(&e, false));
+                            return Err(e);
+                        }
+                    }
+                }
+            };
+
+            // The default sink changed, or the captured application's stream moved or was replaced
+            if capture.changed.replace(false) {
+                capture.reroute(target, &events);
+            }
+
+            if read == 0 {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+
+        let _ = capture.stream.disconnect();
+        Ok(())
+    }
+}
+
+// A recording stream with the connection it lives on, fields drop in this order
+struct Capture {
+    stream: PulseStream,
+    route: Route,
+    // Raised by server, sink and sink input changes
+    changed: Rc<Cell<bool>>,
+    connection: Connection,
+}
+
+impl Capture {
+    fn open(server_name: Option<&str>, target: &CaptureTarget) -> Result<Self, CaptureStartError> {
+        let mut connection = Connection::new(server_name).map_err(CaptureStartError::backend)?;
+        let changed = connection.subscribe().map_err(CaptureStartError::backend)?;
+        let route = connection
+            .resolve(target)
+            .map_err(CaptureStartError::no_device)?;
+        let stream = connection.record(&route).map_err(CaptureStartError::backend)?;
+        Ok(Self { stream, route, changed, connection })
+    }
+
+    // Moves what the server delivered into the buffer, errors when the capture is broken
+    fn read(&mut self, frames: &mut FrameSender) -> Result<usize> {
+        self.connection.iterate(false)?;
+        if !matches!(self.connection.context.get_state(), ContextState::Ready) {
+            return Err(anyhow!("Lost the connection to the audio server"));
+        }
+
+        match self.stream.get_state() {
+            StreamState::Ready => read_available(&mut self.stream, frames),
+            // An application's stream ending is expected, reroute picks up its next one
+            StreamState::Failed | StreamState::Terminated if self.route.sink_input.is_none() => {
+                Err(anyhow!("Recording from {} stopped", self.route.source))
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn reroute(&mut self, target: &CaptureTarget, events: &UnboundedSender<CaptureEvent>) {
+        match self.connection.resolve(target) {
+            Ok(route) if route != self.route => match self.connection.record(&route) {
+                Ok(stream) => {
+                    let _ = self.stream.disconnect();
+                    self.stream = stream;
+                    tracing::info!(source = %route.source, "system_audio_rerouted");
+                    let _ = events.send(CaptureEvent::device_changed(&route.source));
+                    self.route = route;
+                }
+                Err(e) => eprintln!("Failed to switch capture to {}: {}", route.source, e),
+            },
+            Ok(_) => {}
+            // Usually the application stopped playing, keep waiting for its next stream
+            Err(e) => eprintln!("Capture target unavailable: {}", e),
+        }
+    }
+}
+
+// Monitor source to record, narrowed to one application's stream when set
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Route {
+    source: String,
+    sink_input: Option<u32>,
+}
+
+struct SinkEntry {
+    index: u32,
+    name: String,
+    monitor_source: Option<String>,
+}
+
+// PulseAudio objects aren't Send, a connection lives and dies on one thread.
+// The context is declared first so it is dropped before its mainloop.
+struct Connection {
+    context: Context,
+    mainloop: Mainloop,
+}
+
+impl Connection {
+    fn new(server_name: Option<&str>) -> Result<Self> {
+        let mut mainloop = Mainloop::new().ok_or_else(|| anyhow!("Failed to create PulseAudio mainloop"))?;
+        let mut context = Context::new(&mainloop, "pluely")
+            .ok_or_else(|| anyhow!("Failed to create PulseAudio context"))?;
+        context
+            .connect(server_name, ContextFlagSet::NOFLAGS, None)
+            .map_err(|e| anyhow!("Failed to connect to PulseAudio: {}", e))?;
+
+        loop {
+            iterate(&mut mainloop, true)?;
+            match context.get_state() {
+                ContextState::Ready => break,
+                ContextState::Failed | ContextState::Terminated => {
+                    return Err(anyhow!("PulseAudio connection failed"));
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { context, mainloop })
+    }
+
+    fn iterate(&mut self, block: bool) -> Result<()> {
+        iterate(&mut self.mainloop, block)
+    }
+
+    // Runs the mainloop until the operation's callbacks are done
+    fn wait<C: ?Sized>(&mut self, operation: Operation<C>) -> Result<()> {
+        while operation.get_state() == OperationState::Running {
+            self.iterate(true)?;
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<Rc<Cell<bool>>> {
+        let changed = Rc::new(Cell::new(false));
+        let changed_clone = changed.clone();
+        self.context
+            .set_subscribe_callback(Some(Box::new(move |_, _, _| changed_clone.set(true))));
+        let operation = self.context.subscribe(
+            InterestMaskSet::SERVER | InterestMaskSet::SINK | InterestMaskSet::SINK_INPUT,
+            |_| {},
+        );
+        self.wait(operation)?;
+        Ok(changed)
+    }
+
+    fn default_sink(&mut self) -> Result<Option<String>> {
+        let result = Rc::new(RefCell::new(None));
+        let result_clone = result.clone();
+        let operation = self.context.introspect().get_server_info(move |info| {
+            *result_clone.borrow_mut() = info.default_sink_name.as_ref().map(|name| name.to_string());
+        });
+        self.wait(operation)?;
+        Ok(result.take())
+    }
+
+    fn sinks(&mut self) -> Result<Vec<SinkEntry>> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result_clone = result.clone();
+        let operation = self.context.introspect().get_sink_info_list(move |item| {
+            if let ListResult::Item(info) = item {
+                result_clone.borrow_mut().push(SinkEntry {
+                    index: info.index,
+                    name: info.name.as_deref().unwrap_or_default().to_string(),
+                    monitor_source: info.monitor_source_name.as_ref().map(|name| name.to_string()),
+                });
+            }
+        });
+        self.wait(operation)?;
+        Ok(result.take())
+    }
+
+    fn monitors(&mut self, default_sink: Option<&str>) -> Result<Vec<MonitorSource>> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result_clone = result.clone();
+        let default_sink = default_sink.map(str::to_string);
+        let operation = self.context.introspect().get_source_info_list(move |item| {
+            let ListResult::Item(info) = item else { return };
+            // Only monitors, microphones go through get_input_devices
+            if info.monitor_of_sink.is_none() {
+                return;
+            }
+            let sink_name = info.monitor_of_sink_name.as_ref().map(|name| name.to_string());
+            result_clone.borrow_mut().push(MonitorSource {
+                name: info.name.as_deref().unwrap_or_default().to_string(),
+                description: info.description.as_deref().unwrap_or_default().to_string(),
+                is_default: sink_name.is_some() && sink_name == default_sink,
+                sink_name,
+            });
+        });
+        self.wait(operation)?;
+        Ok(result.take())
+    }
+
+    fn applications(&mut self, sinks: &[SinkEntry]) -> Result<Vec<ApplicationStream>> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result_clone = result.clone();
+        let operation = self.context.introspect().get_sink_input_info_list(move |item| {
+            if let ListResult::Item(info) = item {
+                let media_name = info.name.as_ref().map(|name| name.to_string());
+                result_clone.borrow_mut().push((
+                    info.index,
+                    info.sink,
+                    info.proplist.get_str(properties::APPLICATION_NAME),
+                    info.proplist.get_str(properties::APPLICATION_PROCESS_BINARY),
+                    media_name,
+                ));
+            }
+        });
+        self.wait(operation)?;
+
+        let applications = result
+            .take()
+            .into_iter()
+            .map(|(index, sink, application, binary, media_name)| ApplicationStream {
+                index,
+                application: application.or_else(|| binary.clone()).unwrap_or_default(),
+                binary,
+                media_name,
+                sink_name: sinks.iter().find(|s| s.index == sink).map(|s| s.name.clone()),
+            })
+            .collect();
+        Ok(applications)
+    }
+
+    fn capture_targets(&mut self) -> Result<CaptureTargets> {
+        let default_sink = self.default_sink()?;
+        let sinks = self.sinks()?;
+        Ok(CaptureTargets {
+            monitors: self.monitors(default_sink.as_deref())?,
+            applications: self.applications(&sinks)?,
+        })
+    }
+
+    // Where the target can be recorded right now
+    fn resolve(&mut self, target: &CaptureTarget) -> Result<Route> {
+        let sinks = self.sinks()?;
+        let monitor_of = |sink_name: &str| {
+            sinks
+                .iter()
+                .find(|sink| sink.name == sink_name)
+                .and_then(|sink| sink.monitor_source.clone())
+                .ok_or_else(|| anyhow!("Output {} has no monitor source", sink_name))
+        };
+
+        match target {
+            CaptureTarget::DefaultOutput => {
+                let sink = self.default_sink()?.ok_or_else(|| anyhow!("No default output device"))?;
+                Ok(Route { source: monitor_of(&sink)?, sink_input: None })
+            }
+            CaptureTarget::Monitor { name } => Ok(Route { source: name.clone(), sink_input: None }),
+            CaptureTarget::Application { name } => {
+                let application = self
+                    .applications(&sinks)?
+                    .into_iter()
+                    .find(|app| app.application.eq_ignore_ascii_case(name) || app.binary.as_deref() == Some(name.as_str()))
+                    .ok_or_else(|| anyhow!("{} is not playing any audio", name))?;
+                let sink = application
+                    .sink_name
+                    .ok_or_else(|| anyhow!("{} is not playing to an output", name))?;
+                Ok(Route { source: monitor_of(&sink)?, sink_input: Some(application.index) })
+            }
+        }
+    }
+
+    fn record(&mut self, route: &Route) -> Result<PulseStream> {
         let spec = Spec {
             format: Format::F32le,
             channels: 1,
-            rate: 16000,
+            rate: SAMPLE_RATE,
         };
-        
         if !spec.is_valid() {
             return Err(anyhow!("Invalid audio specification"));
         }
 
-        // Try to get the default sink monitor source
-        let source_name = get_default_monitor_source();
-
-        let init_result: Result<(Simple, u32)> = (|| {
-            let simple = Simple::new(
-                None,                        // Use default server
-                "pluely",           // Application name
-                Direction::Record,          // Record direction
-                source_name.as_deref(),     // Source name (monitor)
-                "System Audio Capture",     // Stream description
-                &spec,                      // Sample specification
-                None,                       // Channel map (use default)
-                None,                       // Buffer attributes (use default)
-            ).map_err(|e| anyhow!("Failed to create PulseAudio simple connection: {}", e))?;
-
-            Ok((simple, spec.rate))
-        })();
-
-        match init_result {
-            Ok((simple, sample_rate)) => {
-                let _ = init_tx.send(Ok(sample_rate));
-
-                // Buffer for reading audio data
-                let mut buffer = vec![0u8; 4096]; // 1024 f32 samples * 4 bytes each
-
-                loop {
-                    if waker_state.lock().unwrap().shutdown {
-                        break;
-                    }
+        let mut stream = PulseStream::new(&mut self.context, "System Audio Capture", &spec, None)
+            .ok_or_else(|| anyhow!("Failed to create PulseAudio stream"))?;
+        if let Some(sink_input) = route.sink_input {
+            // Record only this application's stream from the sink monitor
+            stream
+                .set_monitor_stream(sink_input)
+                .map_err(|e| anyhow!("Failed to select application stream: {}", e))?;
+        }
 
-                    match simple.read(&mut buffer) {
-                        Ok(_) => {
-                            // Convert byte buffer to f32 samples
-                            let samples: Vec<f32> = buffer
-                                .chunks_exact(4)
-                                .map(|chunk| {
-                                    f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])
-                                })
-                                .collect();
-
-                            if !samples.is_empty() {
-                                sample_queue.lock().unwrap().extend(samples);
-                                if let Some(waker) = waker_state.lock().unwrap().waker.take() {
-                                    waker.wake();
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("PulseAudio read error: {}", e);
-                            thread::sleep(std::time::Duration::from_millis(100));
-                        }
-                    }
+        let attr = BufferAttr {
+            maxlength: u32::MAX,
+            tlength: u32::MAX,
+            prebuf: u32::MAX,
+            minreq: u32::MAX,
+            fragsize: SAMPLE_RATE * FRAGMENT_MS / 1000 * 4,
+        };
+        stream
+            .connect_record(Some(route.source.as_str()), Some(&attr), StreamFlagSet::ADJUST_LATENCY)
+            .map_err(|e| anyhow!("Failed to record from {}: {}", route.source, e))?;
+
+        loop {
+            self.iterate(true)?;
+            match stream.get_state() {
+                StreamState::Ready => return Ok(stream),
+                StreamState::Failed | StreamState::Terminated => {
+                    return Err(anyhow!("Failed to record from {}", route.source));
                 }
-            }
-            Err(e) => {
-                let _ = init_tx.send(Err(e));
+                _ => {}
             }
         }
-        Ok(())
     }
 }
 
-fn get_default_monitor_source() -> Option<String> {
-    Some("@DEFAULT_MONITOR@".to_string())
+fn iterate(mainloop: &mut Mainloop, block: bool) -> Result<()> {
+    match mainloop.iterate(block) {
+        IterateResult::Success(_) => Ok(()),
+        IterateResult::Quit(_) => Err(anyhow!("PulseAudio mainloop quit")),
+        IterateResult::Err(e) => Err(anyhow!("PulseAudio mainloop error: {}", e)),
+    }
+}
+
+// Moves everything the server has delivered into the buffer, returns the sample count
+fn read_available(stream: &mut PulseStream, frames: &mut FrameSender) -> Result<usize> {
+    let mut total = 0;
+    loop {
+        let samples: Vec<f32> = match stream.peek().map_err(|e| anyhow!("{}", e))? {
+            PeekResult::Empty => break,
+            PeekResult::Hole(_) => Vec::new(),
+            PeekResult::Data(data) => data
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        };
+        stream.discard().map_err(|e| anyhow!("{}", e))?;
+
+        // Counted even when the buffer was full, so an overrun doesn't look like silence
+        total += samples.len();
+        frames.push(&samples);
+    }
+    Ok(total)
 }
 
 impl Drop for SpeakerStream {
     fn drop(&mut self) {
-        {
-            let mut state = self.waker_state.lock().unwrap();
-            state.shutdown = true;
-            if let Some(waker) = state.waker.take() {
-                waker.wake();
-            }
-        }
+        self.frames.close();
         if let Some(thread) = self.capture_thread.take() {
             let _ = thread.join();
         }
@@ -183,23 +511,12 @@ impl Drop for SpeakerStream {
 }
 
 impl Stream for SpeakerStream {
-    type Item = f32;
+    type Item = Vec<f32>;
 
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
+        mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        let mut queue = self.sample_queue.lock().unwrap();
-        if let Some(sample) = queue.pop_front() {
-            return Poll::Ready(Some(sample));
-        }
-
-        let mut state = self.waker_state.lock().unwrap();
-        if state.shutdown {
-            return Poll::Ready(None);
-        }
-
-        state.waker = Some(cx.waker().clone());
-        Poll::Pending
+        std::pin::Pin::new(&mut self.frames).poll_next(cx)
     }
-}
\ No newline at end of file
+}
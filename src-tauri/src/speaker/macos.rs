@@ -1,33 +1,26 @@
 // Pluely macos speaker input and stream
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
-use std::task::{Poll, Waker};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
 
 use anyhow::Result;
 use futures_util::Stream;
-use ringbuf::{
-    traits::{Consumer, Producer, Split},
-    HeapCons, HeapProd, HeapRb,
-};
 
 use ca::aggregate_device_keys as agg_keys;
 use cidre::{arc, av, cat, cf, core_audio as ca, ns, os};
+
+use super::frames::{frame_channel, BufferStats, FrameReceiver, FrameSender};
+use super::CaptureStartError;
 pub struct SpeakerInput {
     tap: ca::TapGuard,  // Assuming ca::TapGuard from core-audio-rs
     agg_desc: arc::Retained<cf::DictionaryOf<cf::String, cf::Type>>,
 }
 
-struct WakerState {
-    waker: Option<Waker>,
-    has_data: bool,
-}
-
 pub struct SpeakerStream {
-    consumer: HeapCons<f32>,
+    frames: FrameReceiver,
     _device: ca::hardware::StartedDevice<ca::AggregateDevice>,
     _ctx: Box<Ctx>,
     _tap: ca::TapGuard,
-    waker_state: Arc<Mutex<WakerState>>,
     current_sample_rate: Arc<AtomicU32>,
 }
 
@@ -35,15 +28,17 @@ impl SpeakerStream {
     pub fn sample_rate(&self) -> u32 {
         self.current_sample_rate.load(Ordering::Acquire)
     }
+
+    pub fn stats(&self) -> Arc<BufferStats> {
+        self.frames.stats()
+    }
 }
 
 struct Ctx {
     format: arc::R<av::AudioFormat>,
-    producer: HeapProd<f32>,
-    waker_state: Arc<Mutex<WakerState>>,
+    frames: FrameSender,
     current_sample_rate: Arc<AtomicU32>,
     consecutive_drops: Arc<AtomicU32>,
-    should_terminate: Arc<AtomicBool>,
 }
 
 impl SpeakerInput {
@@ -148,106 +143,57 @@ impl SpeakerInput {
         Ok(started_device)
     }
 
-    pub fn stream(self) -> SpeakerStream {
-        let asbd = self.tap.asbd().unwrap();
+    pub fn stream(self) -> Result<SpeakerStream, CaptureStartError> {
+        let asbd = self.tap.asbd().map_err(CaptureStartError::backend)?;
 
-        let format = av::AudioFormat::with_asbd(&asbd).unwrap();
+        let format = av::AudioFormat::with_asbd(&asbd)
+            .ok_or_else(|| CaptureStartError::backend("Unsupported tap audio format"))?;
 
-        let buffer_size = 1024 * 128;
-        let rb = HeapRb::<f32>::new(buffer_size);
-        let (producer, consumer) = rb.split();
-
-        let waker_state = Arc::new(Mutex::new(WakerState {
-            waker: None,
-            has_data: false,
-        }));
+        let (sender, frames) = frame_channel(asbd.sample_rate as u32);
 
         let current_sample_rate = Arc::new(AtomicU32::new(asbd.sample_rate as u32));
 
         let mut ctx = Box::new(Ctx {
             format,
-            producer,
-            waker_state: waker_state.clone(),
+            frames: sender,
             current_sample_rate: current_sample_rate.clone(),
             consecutive_drops: Arc::new(AtomicU32::new(0)),
-            should_terminate: Arc::new(AtomicBool::new(false)),
         });
 
-        let device = self.start_device(&mut ctx).unwrap();
+        let device = self.start_device(&mut ctx).map_err(CaptureStartError::backend)?;
 
-        SpeakerStream {
-            consumer,
+        Ok(SpeakerStream {
+            frames,
             _device: device,
             _ctx: ctx,
             _tap: self.tap,
-            waker_state,
             current_sample_rate,
-        }
+        })
     }
 }
 
 fn process_audio_data(ctx: &mut Ctx, data: &[f32]) {
-    let buffer_size = data.len();
-    let pushed = ctx.producer.push_slice(data);
+    let pushed = ctx.frames.push(data);
 
-    if pushed < buffer_size {
+    if pushed < data.len() {
         let consecutive = ctx.consecutive_drops.fetch_add(1, Ordering::AcqRel) + 1;
 
+        // The stream stopped being read, end it after the buffered frames
         if consecutive > 10 {
-            ctx.should_terminate.store(true, Ordering::Release);
-            return;
+            ctx.frames.close();
         }
     } else {
         ctx.consecutive_drops.store(0, Ordering::Release);
     }
-
-    if pushed > 0 {
-        let should_wake = {
-            let mut waker_state = ctx.waker_state.lock().unwrap();
-            if !waker_state.has_data {
-                waker_state.has_data = true;
-                waker_state.waker.take()
-            } else {
-                None
-            }
-        };
-
-        if let Some(waker) = should_wake {
-            waker.wake();
-        }
-    }
 }
 
 impl Stream for SpeakerStream {
-    type Item = f32;
+    type Item = Vec<f32>;
 
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        if let Some(sample) = self.consumer.try_pop() {
-            return Poll::Ready(Some(sample));
-        }
-
-        if self._ctx.should_terminate.load(Ordering::Acquire) {
-            return match self.consumer.try_pop() {
-                Some(sample) => Poll::Ready(Some(sample)),
-                None => Poll::Ready(None),
-            };
-        }
-
-        {
-            let mut state = self.waker_state.lock().unwrap();
-            state.has_data = false;
-            state.waker = Some(cx.waker().clone());
-        }
-
-        Poll::Pending
+        std::pin::Pin::new(&mut self.frames).poll_next(cx)
     }
 }
-
-impl Drop for SpeakerStream {
-    fn drop(&mut self) {
-        self._ctx.should_terminate.store(true, Ordering::Release);
-    }
-}
\ No newline at end of file
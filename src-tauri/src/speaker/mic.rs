@@ -0,0 +1,190 @@
+// Pluely microphone input and stream, captured with cpal on every platform
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+use futures_util::Stream;
+use serde::Serialize;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+
+use super::frames::{frame_channel, BufferStats, FrameReceiver, FrameSender};
+use super::CaptureStartError;
+
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioDevice {
+    // cpal has no stable device ids, the name is what selects a device
+    name: String,
+    is_default: bool,
+}
+
+/// Lists the input devices of the default host.
+pub fn list_input_devices() -> Result<Vec<AudioDevice>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+
+    let devices = host
+        .input_devices()
+        .map_err(|e| anyhow!("Failed to enumerate input devices: {}", e))?
+        .filter_map(|device| device.name().ok())
+        .map(|name| AudioDevice {
+            is_default: Some(&name) == default_name.as_ref(),
+            name,
+        })
+        .collect();
+    Ok(devices)
+}
+
+pub struct MicInput {
+    device: Device,
+}
+
+impl MicInput {
+    // Opens the named input device, or the system default one
+    pub fn new(device_name: Option<&str>) -> Result<Self> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()
+                .map_err(|e| anyhow!("Failed to enumerate input devices: {}", e))?
+                .find(|device| device.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| anyhow!("Input device not found: {}", name))?,
+            None => host
+                .default_input_device()
+                .ok_or_else(|| anyhow!("No default input device"))?,
+        };
+        Ok(Self { device })
+    }
+
+    pub fn stream(self) -> Result<MicStream> {
+        let (init_tx, init_rx) = mpsc::channel();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
+        let device = self.device;
+
+        // cpal streams aren't Send on every platform, so the stream lives on its own thread
+        let capture_thread = thread::spawn(move || {
+            let _stream = match build_stream(&device) {
+                Ok((stream, frames, sample_rate)) => {
+                    let _ = init_tx.send(Ok((frames, sample_rate)));
+                    stream
+                }
+                Err(e) => {
+                    let _ = init_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            // Captures until MicStream is dropped, the cpal stream stops with this thread
+            let _ = shutdown_rx.recv();
+        });
+
+        let (frames, sample_rate) = match init_rx.recv_timeout(INIT_TIMEOUT) {
+            Ok(started) => started?,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // The driver may be stuck opening the device, so the thread is left to exit
+                // on its own: with the shutdown channel closed it drops the stream once built
+                drop(shutdown_tx);
+                return Err(CaptureStartError::Timeout.into());
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Microphone thread exited during startup"));
+            }
+        };
+
+        Ok(MicStream {
+            frames,
+            shutdown_tx: Some(shutdown_tx),
+            capture_thread: Some(capture_thread),
+            sample_rate,
+        })
+    }
+}
+
+fn build_stream(device: &Device) -> Result<(cpal::Stream, FrameReceiver, u32)> {
+    let supported = device
+        .default_input_config()
+        .map_err(|e| anyhow!("Failed to get input config: {}", e))?;
+    let config: StreamConfig = supported.config();
+    let sample_rate = config.sample_rate.0;
+    let (sender, frames) = frame_channel(sample_rate);
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_typed_stream::<f32>(device, &config, sender),
+        SampleFormat::I16 => build_typed_stream::<i16>(device, &config, sender),
+        SampleFormat::U16 => build_typed_stream::<u16>(device, &config, sender),
+        SampleFormat::I32 => build_typed_stream::<i32>(device, &config, sender),
+        format => return Err(anyhow!("Unsupported input sample format: {}", format)),
+    }?;
+
+    stream
+        .play()
+        .map_err(|e| anyhow!("Failed to start microphone stream: {}", e))?;
+    Ok((stream, frames, sample_rate))
+}
+
+fn build_typed_stream<T>(device: &Device, config: &StreamConfig, mut sender: FrameSender) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut mono = Vec::new();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                // Downmix by averaging the channels of each frame
+                mono.clear();
+                mono.extend(data.chunks(channels).map(|frame| {
+                    frame.iter().map(|&s| s.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+                }));
+                sender.push(&mono);
+            },
+            |e| eprintln!("Microphone stream error: {}", e),
+            None,
+        )
+        .map_err(|e| anyhow!("Failed to build microphone stream: {}", e))
+}
+
+// Stream of fixed-size frames of mono f32 samples from the microphone
+pub struct MicStream {
+    frames: FrameReceiver,
+    shutdown_tx: Option<mpsc::Sender<()>>,
+    capture_thread: Option<thread::JoinHandle<()>>,
+    sample_rate: u32,
+}
+
+impl MicStream {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn stats(&self) -> Arc<BufferStats> {
+        self.frames.stats()
+    }
+}
+
+impl Drop for MicStream {
+    fn drop(&mut self) {
+        // Closing the channel wakes the capture thread, which drops the cpal stream
+        self.shutdown_tx.take();
+        if let Some(thread) = self.capture_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Stream for MicStream {
+    type Item = Vec<f32>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.frames).poll_next(cx)
+    }
+}
@@ -1,6 +1,11 @@
 use anyhow::Result;
 use futures_util::{Stream};
+use serde::{Deserialize, Serialize};
 use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::error::{PluelyError, PluelyResult};
 
 #[cfg(target_os = "macos")]
 mod macos;
@@ -18,7 +23,199 @@ mod linux;
 use linux::{SpeakerInput as PlatformSpeakerInput, SpeakerStream as PlatformSpeakerStream};
 
 mod commands;
+mod echo;
+mod frames;
+mod mic;
+pub(crate) mod resample;
+mod settings;
+mod transcript;
+mod vad;
 pub use commands::*;
+pub use settings::load_vad_settings;
+
+// What system audio capture records. Only Linux can capture anything but the default output.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureTarget {
+    // Monitor of the default output, follows it when the default changes
+    #[default]
+    DefaultOutput,
+    // A monitor source from `get_capture_targets`
+    Monitor { name: String },
+    // Only one application's audio, matched by application or binary name
+    Application { name: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorSource {
+    name: String,
+    description: String,
+    // Output device this monitors
+    sink_name: Option<String>,
+    is_default: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplicationStream {
+    index: u32,
+    application: String,
+    binary: Option<String>,
+    media_name: Option<String>,
+    sink_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureTargets {
+    monitors: Vec<MonitorSource>,
+    applications: Vec<ApplicationStream>,
+}
+
+// Payload of `audio-device-changed`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceChange {
+    // What the capture records now
+    device: String,
+}
+
+// Payload of `audio-capture-error`
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureError {
+    message: String,
+    // False once the capture gave up and stopped
+    recovering: bool,
+}
+
+// Payload of `capture-stopped`
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureStopped {
+    reason: String,
+}
+
+// Reported by the platform capture threads while they run
+#[derive(Debug, Clone)]
+pub enum CaptureEvent {
+    DeviceChanged(DeviceChange),
+    Error(CaptureError),
+    // The capture thread exited without the stream being dropped, always the last event
+    Stopped(CaptureStopped),
+}
+
+impl CaptureEvent {
+    pub(crate) fn device_changed(device: impl Into<String>) -> Self {
+        CaptureEvent::DeviceChanged(DeviceChange { device: device.into() })
+    }
+
+    pub(crate) fn error(message: impl std::fmt::Display, recovering: bool) -> Self {
+        CaptureEvent::Error(CaptureError { message: message.to_string(), recovering })
+    }
+
+    pub(crate) fn stopped(reason: impl std::fmt::Display) -> Self {
+        CaptureEvent::Stopped(CaptureStopped { reason: reason.to_string() })
+    }
+}
+
+pub type CaptureEvents = UnboundedReceiver<CaptureEvent>;
+
+// Why system audio capture couldn't start
+#[derive(Debug)]
+pub enum CaptureStartError {
+    // Nothing to record, e.g. no output device or the target application isn't playing
+    NoDevice(String),
+    // The audio server or platform API refused to open the capture
+    Backend(String),
+    // The capture thread didn't report back in time
+    Timeout,
+    Unsupported,
+}
+
+impl CaptureStartError {
+    pub(crate) fn no_device(message: impl std::fmt::Display) -> Self {
+        CaptureStartError::NoDevice(message.to_string())
+    }
+
+    pub(crate) fn backend(message: impl std::fmt::Display) -> Self {
+        CaptureStartError::Backend(message.to_string())
+    }
+}
+
+impl std::fmt::Display for CaptureStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDevice(message) => write!(f, "No audio to capture: {}", message),
+            Self::Backend(message) => write!(f, "Failed to start audio capture: {}", message),
+            Self::Timeout => f.write_str("Audio capture did not start in time"),
+            Self::Unsupported => f.write_str("System audio capture is not supported on this platform"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureStartError {}
+
+impl From<CaptureStartError> for PluelyError {
+    fn from(error: CaptureStartError) -> Self {
+        match error {
+            CaptureStartError::Unsupported => PluelyError::unsupported(error.to_string()),
+            _ => PluelyError::audio(error.to_string()),
+        }
+    }
+}
+
+// Message of a panic caught on a capture thread
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Capture thread panicked".to_string())
+}
+
+// Failed captures are reopened this many times before the capture stops
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+const MAX_RECOVERY_ATTEMPTS: u32 = 8;
+
+/// Reopens a failed capture, backing off from 250ms to 4s between attempts.
+/// Gives up after MAX_RECOVERY_ATTEMPTS, or as soon as the stream is dropped.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn reopen_with_backoff<T>(
+    is_shutdown: impl Fn() -> bool,
+    mut open: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    use std::time::{Duration, Instant};
+
+    let mut last_error = anyhow::anyhow!("Capture stopped");
+    for attempt in 0..MAX_RECOVERY_ATTEMPTS {
+        let backoff = Duration::from_millis(250 << attempt.min(4));
+        // Sleep in slices so dropping the stream doesn't wait out the backoff
+        let deadline = Instant::now() + backoff;
+        while Instant::now() < deadline {
+            if is_shutdown() {
+                return Err(anyhow::anyhow!("Capture stopped"));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        match open() {
+            Ok(capture) => return Ok(capture),
+            Err(e) => {
+                eprintln!("Capture recovery attempt {} failed: {}", attempt + 1, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Lists what system audio capture can record on this platform.
+#[cfg(target_os = "linux")]
+pub fn list_capture_targets() -> PluelyResult<CaptureTargets> {
+    linux::list_capture_targets().map_err(|e| PluelyError::audio(e.to_string()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn list_capture_targets() -> PluelyResult<CaptureTargets> {
+    Err(PluelyError::unsupported("Choosing what to capture is only supported on Linux"))
+}
 
 // Pluely speaker input and stream
 pub struct SpeakerInput {
@@ -39,26 +236,41 @@ impl SpeakerInput {
         Err(anyhow::anyhow!("SpeakerInput::new is not supported on this platform"))
     }
 
-    // Starts the audio stream.
+    // Creates a speaker input recording the given target.
+    #[cfg(target_os = "linux")]
+    pub fn with_target(target: CaptureTarget) -> Result<Self> {
+        let inner = PlatformSpeakerInput::with_target(target)?;
+        Ok(Self { inner })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn with_target(target: CaptureTarget) -> Result<Self> {
+        if target != CaptureTarget::default() {
+            return Err(anyhow::anyhow!("Capture targets are only supported on Linux"));
+        }
+        Self::new()
+    }
+
+    // Starts the audio stream, fails when the platform capture couldn't be opened.
     #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
-    pub fn stream(self) -> SpeakerStream {
-        let inner = self.inner.stream();
-        SpeakerStream { inner }
+    pub fn stream(self) -> Result<SpeakerStream, CaptureStartError> {
+        let inner = self.inner.stream()?;
+        Ok(SpeakerStream { inner })
     }
 
     #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
-    pub fn stream(self) -> SpeakerStream {
-        unimplemented!("SpeakerInput::stream is not supported on this platform")
+    pub fn stream(self) -> Result<SpeakerStream, CaptureStartError> {
+        Err(CaptureStartError::Unsupported)
     }
 }
 
-// Stream of f32 audio samples from the speaker.
+// Stream of fixed-size frames (20ms) of f32 audio samples from the speaker.
 pub struct SpeakerStream {
     inner: PlatformSpeakerStream,
 }
 
 impl Stream for SpeakerStream {
-    type Item = f32;
+    type Item = Vec<f32>;
 
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
@@ -77,6 +289,20 @@ impl Stream for SpeakerStream {
 }
 
 impl SpeakerStream {
+    // Device changes and errors of the running capture, can only be taken once.
+    pub fn take_events(&mut self) -> Option<CaptureEvents> {
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        return self.inner.take_events();
+
+        #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+        None
+    }
+
+    // Buffer counters, shared so they can be read while the pipeline owns the stream.
+    pub fn stats(&self) -> Arc<frames::BufferStats> {
+        self.inner.stats()
+    }
+
     // Gets the sample rate (e.g., 16000 Hz on stub, variable on real impls).
     pub fn sample_rate(&self) -> u32 {
         #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
@@ -0,0 +1,326 @@
+// Pluely resampling, brings every capture to the 16 kHz that speech-to-text expects
+// with a Kaiser-windowed sinc filter evaluated at arbitrary fractional positions
+use futures_util::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// Rate of everything after the resampler: VAD, WAV uploads and local whisper
+pub const TARGET_SAMPLE_RATE: u32 = 16000;
+
+const ZERO_CROSSINGS: usize = 16;  // Sinc lobes on each side, sets the transition band width
+const PHASES: usize = 256;  // Filter table resolution per zero crossing, interpolated in between
+const KAISER_BETA: f64 = 8.0;  // ~80 dB stopband
+const ROLLOFF: f64 = 0.92;  // Cutoff as a share of the lower Nyquist, keeps the transition band out of aliasing
+const COMPACT_AT: usize = 4096;  // Consumed input dropped in batches rather than per sample
+
+pub struct Resampler {
+    // Input samples per output sample
+    step: f64,
+    // Filter cutoff relative to the input Nyquist
+    cutoff: f64,
+    // Input samples on each side of an output position
+    half_width: usize,
+    // Half of the windowed sinc, PHASES entries per zero crossing
+    table: Vec<f32>,
+    input: Vec<f32>,
+    // Position of the next output sample in `input`
+    position: f64,
+    // Totals since the start, so a flush knows how much output the input is worth
+    fed: u64,
+    produced: u64,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        let step = from_rate.max(1) as f64 / to_rate.max(1) as f64;
+        let cutoff = (1.0 / step).min(1.0) * ROLLOFF;
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        let len = ZERO_CROSSINGS * PHASES;
+        let i0_beta = bessel_i0(KAISER_BETA);
+        let table = (0..=len + 1)
+            .map(|i| {
+                let x = i as f64 / PHASES as f64;
+                let ratio = (i as f64 / len as f64).min(1.0);
+                let window = bessel_i0(KAISER_BETA * (1.0 - ratio * ratio).sqrt()) / i0_beta;
+                (sinc(x) * window) as f32
+            })
+            .collect();
+
+        Self {
+            step,
+            cutoff,
+            half_width,
+            table,
+            // Silence before the first sample, so output starts with the input instead of after the filter delay
+            input: vec![0.0; half_width],
+            position: half_width as f64,
+            fed: 0,
+            produced: 0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    /// Appends the resampled output for `samples`, keeping the filter state between calls.
+    pub fn process(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(samples);
+            return;
+        }
+
+        self.input.extend_from_slice(samples);
+        self.fed += samples.len() as u64;
+        while self.position + (self.half_width as f64) < self.input.len() as f64 {
+            output.push(self.sample_at(self.position));
+            self.position += self.step;
+            self.produced += 1;
+        }
+
+        let consumed = (self.position as usize).saturating_sub(self.half_width);
+        if consumed >= COMPACT_AT {
+            self.input.drain(..consumed);
+            self.position -= consumed as f64;
+        }
+    }
+
+    /// Appends the samples still waiting for their right-hand neighbours, as if the
+    /// input continued with silence. Nothing should be processed afterwards.
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            return;
+        }
+
+        let expected = (self.fed as f64 / self.step).round() as u64;
+        let remaining = expected.saturating_sub(self.produced) as usize;
+        let start = output.len();
+        self.process(&vec![0.0; self.half_width + 1], output);
+        output.truncate(start + remaining);
+    }
+
+    fn sample_at(&self, position: f64) -> f32 {
+        let center = position.floor() as usize;
+        let first = center + 1 - self.half_width;
+        let last = (center + self.half_width).min(self.input.len() - 1);
+
+        let mut sum = 0.0f32;
+        for (j, &x) in self.input[first..=last].iter().enumerate() {
+            let distance = (position - (first + j) as f64).abs() * self.cutoff;
+            sum += x * self.kernel(distance);
+        }
+        sum * self.cutoff as f32
+    }
+
+    // Windowed sinc at `distance` zero crossings, linearly interpolated from the table
+    fn kernel(&self, distance: f64) -> f32 {
+        let index = distance * PHASES as f64;
+        let i = index as usize;
+        if i >= ZERO_CROSSINGS * PHASES {
+            return 0.0;
+        }
+        let frac = (index - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * frac
+    }
+}
+
+/// Resamples a whole clip in one go.
+#[cfg(any(feature = "local-stt", test))]
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    let mut resampler = Resampler::new(from_rate, to_rate);
+    let mut output = Vec::with_capacity((samples.len() as f64 / resampler.step) as usize + 1);
+    resampler.process(samples, &mut output);
+    resampler.flush(&mut output);
+    output
+}
+
+// Capture stream resampled to TARGET_SAMPLE_RATE, frame by frame
+pub struct ResampledStream<S> {
+    inner: S,
+    resampler: Resampler,
+    flushed: bool,
+}
+
+impl<S> ResampledStream<S> {
+    pub fn new(inner: S, sample_rate: u32) -> Self {
+        Self {
+            inner,
+            resampler: Resampler::new(sample_rate, TARGET_SAMPLE_RATE),
+            flushed: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = Vec<f32>> + Unpin> Stream for ResampledStream<S> {
+    type Item = Vec<f32>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(frame)) if this.resampler.is_passthrough() => return Poll::Ready(Some(frame)),
+                Poll::Ready(Some(frame)) => {
+                    let mut output = Vec::with_capacity((frame.len() as f64 / this.resampler.step) as usize + 1);
+                    this.resampler.process(&frame, &mut output);
+                    // A frame shorter than the filter can leave nothing to output yet
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(output));
+                    }
+                }
+                // The end of the capture still owes the filter's last half_width samples
+                Poll::Ready(None) if !this.flushed => {
+                    this.flushed = true;
+                    let mut output = Vec::new();
+                    this.resampler.flush(&mut output);
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(output));
+                    }
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+// Zeroth order modified Bessel function of the first kind, for the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= (half / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use std::f64::consts::PI;
+
+    const RATES: [u32; 3] = [44100, 48000, 22050];
+
+    fn sine(frequency: f64, sample_rate: u32, seconds: f64) -> Vec<f32> {
+        let len = (sample_rate as f64 * seconds) as usize;
+        (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin() as f32)
+            .collect()
+    }
+
+    // RMS away from both ends, where the filter sees the silence around the clip
+    fn steady_rms(samples: &[f32]) -> f64 {
+        let edge = samples.len() / 8;
+        let middle = &samples[edge..samples.len() - edge];
+        (middle.iter().map(|&x| (x as f64).powi(2)).sum::<f64>() / middle.len() as f64).sqrt()
+    }
+
+    fn gain_db(input: &[f32], output: &[f32]) -> f64 {
+        20.0 * (steady_rms(output) / steady_rms(input)).log10()
+    }
+
+    #[test]
+    fn tones_keep_their_frequency_and_phase() {
+        for rate in RATES {
+            let output = resample(&sine(440.0, rate, 0.5), rate, TARGET_SAMPLE_RATE);
+            let expected = sine(440.0, TARGET_SAMPLE_RATE, 0.5);
+
+            let edge = output.len() / 8;
+            let max_error = output[edge..output.len() - edge]
+                .iter()
+                .zip(&expected[edge..])
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            assert!(max_error < 1e-3, "{} Hz: max error {}", rate, max_error);
+        }
+    }
+
+    #[test]
+    fn passband_is_flat() {
+        for rate in RATES {
+            for frequency in [100.0, 1000.0, 3000.0] {
+                let input = sine(frequency, rate, 0.5);
+                let gain = gain_db(&input, &resample(&input, rate, TARGET_SAMPLE_RATE));
+                assert!(gain.abs() < 0.1, "{} Hz at {} Hz: {:.3} dB", frequency, rate, gain);
+            }
+        }
+    }
+
+    #[test]
+    fn stopband_is_attenuated() {
+        // Everything above the 8 kHz output Nyquist would alias back into speech
+        for rate in [44100, 48000] {
+            for frequency in [9000.0, 12000.0, 20000.0] {
+                let input = sine(frequency, rate, 0.5);
+                let gain = gain_db(&input, &resample(&input, rate, TARGET_SAMPLE_RATE));
+                assert!(gain < -60.0, "{} Hz at {} Hz: {:.1} dB", frequency, rate, gain);
+            }
+        }
+        let input = sine(10000.0, 22050, 0.5);
+        let gain = gain_db(&input, &resample(&input, 22050, TARGET_SAMPLE_RATE));
+        assert!(gain < -60.0, "10000 Hz at 22050 Hz: {:.1} dB", gain);
+    }
+
+    #[test]
+    fn resample_length_matches_duration() {
+        for rate in RATES {
+            for len in [0, 1, 100, 4410, 48000, 100_003] {
+                let output = resample(&vec![0.5; len], rate, TARGET_SAMPLE_RATE);
+                let expected = (len as f64 * TARGET_SAMPLE_RATE as f64 / rate as f64).round() as usize;
+                assert_eq!(output.len(), expected, "{} samples at {} Hz", len, rate);
+            }
+        }
+        assert_eq!(resample(&[0.25; 10], 16000, 16000), vec![0.25; 10]);
+    }
+
+    #[test]
+    fn chunked_process_matches_whole_clip() {
+        for rate in RATES {
+            let input = sine(1000.0, rate, 0.3);
+            let whole = resample(&input, rate, TARGET_SAMPLE_RATE);
+
+            // Uneven chunks, some shorter than the filter
+            let mut resampler = Resampler::new(rate, TARGET_SAMPLE_RATE);
+            let mut chunked = Vec::new();
+            for chunk in input.chunks(37) {
+                resampler.process(chunk, &mut chunked);
+                resampler.process(&[], &mut chunked);
+            }
+            assert!(chunked.len() < whole.len(), "the filter holds back its last samples");
+            resampler.flush(&mut chunked);
+
+            assert_eq!(chunked.len(), whole.len(), "{} Hz", rate);
+            let max_error = chunked.iter().zip(&whole).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+            assert!(max_error < 1e-6, "{} Hz: chunked output differs by {}", rate, max_error);
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_flushes_at_end() {
+        for rate in RATES {
+            let input = sine(1000.0, rate, 0.25);
+            let frames: Vec<Vec<f32>> = input.chunks(rate as usize / 50).map(<[f32]>::to_vec).collect();
+
+            let output: Vec<f32> = ResampledStream::new(futures_util::stream::iter(frames), rate)
+                .concat()
+                .await;
+            assert_eq!(output.len(), resample(&input, rate, TARGET_SAMPLE_RATE).len(), "{} Hz", rate);
+        }
+    }
+}
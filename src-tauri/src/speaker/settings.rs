@@ -0,0 +1,112 @@
+// Pluely speech segmentation settings, in milliseconds so they mean the same at every sample rate
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tokio::sync::watch;
+
+use crate::error::{PluelyError, PluelyResult};
+use crate::settings_file;
+
+const SETTINGS_FILE: &str = "vad_settings.json";
+// Smallest analysis chunk, the spectral detector needs a few dozen bins
+const MIN_HOP_SIZE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct VadConfig {
+    // Analysis chunk length
+    pub hop_ms: u32,
+    // Silence that ends a segment
+    pub silence_ms: u32,
+    // Shorter segments are dropped as noise
+    pub min_speech_ms: u32,
+    // Audio kept from before speech started
+    pub pre_speech_ms: u32,
+    // Segments are cut here even if speech continues
+    pub max_segment_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            hop_ms: 23,
+            silence_ms: 1000,
+            min_speech_ms: 350,
+            pre_speech_ms: 350,
+            max_segment_ms: 30_000,
+        }
+    }
+}
+
+// VadConfig converted to samples and chunks for one stream
+#[derive(Debug, Clone, Copy)]
+pub struct VadParams {
+    pub hop_size: usize,
+    pub silence_chunks: usize,
+    pub min_speech_chunks: usize,
+    pub pre_speech_chunks: usize,
+    pub max_samples: usize,
+}
+
+impl VadConfig {
+    pub fn validate(&self) -> PluelyResult<()> {
+        for (name, value, min, max) in [
+            ("hopMs", self.hop_ms, 10, 100),
+            ("silenceMs", self.silence_ms, 100, 10_000),
+            ("minSpeechMs", self.min_speech_ms, 0, 5_000),
+            ("preSpeechMs", self.pre_speech_ms, 0, 2_000),
+            ("maxSegmentMs", self.max_segment_ms, 1_000, 120_000),
+        ] {
+            if !(min..=max).contains(&value) {
+                return Err(PluelyError::config(format!("{} must be between {} and {}", name, min, max)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the durations using the stream's real sample rate.
+    pub fn params(&self, sample_rate: u32) -> VadParams {
+        let sample_rate = sample_rate.max(1) as u64;
+        let samples = |ms: u32| (sample_rate * ms as u64 / 1000) as usize;
+
+        let hop_size = samples(self.hop_ms).max(MIN_HOP_SIZE);
+        let chunks = |ms: u32| samples(ms).div_ceil(hop_size);
+
+        VadParams {
+            hop_size,
+            silence_chunks: chunks(self.silence_ms).max(1),
+            min_speech_chunks: chunks(self.min_speech_ms),
+            pre_speech_chunks: chunks(self.pre_speech_ms),
+            max_samples: samples(self.max_segment_ms),
+        }
+    }
+}
+
+// Managed settings, a running capture watches for changes
+pub struct VadSettings(watch::Sender<VadConfig>);
+
+impl VadSettings {
+    pub fn get(&self) -> VadConfig {
+        self.0.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<VadConfig> {
+        self.0.subscribe()
+    }
+
+    /// Validates, persists and applies the config, including to a running capture.
+    pub fn update(&self, app: &AppHandle, config: VadConfig) -> PluelyResult<()> {
+        config.validate()?;
+
+        settings_file::save(app, SETTINGS_FILE, &config)?;
+
+        self.0.send_replace(config);
+        Ok(())
+    }
+}
+
+/// Loads the saved settings at startup, invalid settings fall back to the defaults.
+pub fn load_vad_settings(app: &AppHandle) -> VadSettings {
+    let config = settings_file::load_or_default(app, SETTINGS_FILE, VadConfig::validate);
+
+    VadSettings(watch::channel(config).0)
+}
@@ -0,0 +1,137 @@
+// Pluely incremental transcription of system audio, rolling partial transcripts while speech is ongoing
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::AppHandle;
+
+use super::commands::{samples_to_wav_b64, AudioSource};
+use crate::api::{self, SttEngine};
+use crate::error::PluelyError;
+
+// Faster than this and partials would only queue up behind each other
+const MIN_PARTIAL_INTERVAL_MS: u64 = 300;
+
+// Payload of `transcript-partial` and `transcript-final`, labelled with the source when emitted
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEvent {
+    segment_id: u64,
+    text: String,
+    // Offsets from the start of the capture
+    start_ms: u64,
+    end_ms: u64,
+    error: Option<PluelyError>,
+}
+
+pub struct IncrementalTranscriber {
+    app: AppHandle,
+    source: AudioSource,
+    engine: SttEngine,
+    sample_rate: u32,
+    interval_samples: usize,
+    // Segment length when the last partial was sent
+    last_partial_len: usize,
+    // One partial at a time, a slow backend skips intervals instead of queueing them
+    in_flight: Arc<AtomicBool>,
+    // Highest finalized segment, partials that complete after it are dropped
+    finalized: Arc<AtomicU64>,
+}
+
+impl IncrementalTranscriber {
+    pub fn new(app: AppHandle, source: AudioSource, engine: SttEngine, sample_rate: u32, interval_ms: u64) -> Self {
+        let interval_ms = interval_ms.max(MIN_PARTIAL_INTERVAL_MS);
+        Self {
+            app,
+            source,
+            engine,
+            sample_rate,
+            interval_samples: (sample_rate as u64 * interval_ms / 1000) as usize,
+            last_partial_len: 0,
+            in_flight: Arc::new(AtomicBool::new(false)),
+            finalized: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn to_ms(&self, samples: u64) -> u64 {
+        samples * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    fn event(&self, segment_id: u64, start_sample: u64, len: usize) -> TranscriptEvent {
+        TranscriptEvent {
+            segment_id,
+            text: String::new(),
+            start_ms: self.to_ms(start_sample),
+            end_ms: self.to_ms(start_sample + len as u64),
+            error: None,
+        }
+    }
+
+    /// Transcribes the segment so far once another interval of audio has arrived.
+    pub fn partial(&mut self, segment_id: u64, start_sample: u64, samples: &[f32]) {
+        if samples.len() < self.last_partial_len + self.interval_samples {
+            return;
+        }
+        if self.in_flight.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.last_partial_len = samples.len();
+
+        let audio_base64 = match samples_to_wav_b64(self.sample_rate, samples) {
+            Ok(b64) => b64,
+            Err(e) => {
+                eprintln!("Failed to encode partial segment: {}", e);
+                self.in_flight.store(false, Ordering::Release);
+                return;
+            }
+        };
+
+        let app = self.app.clone();
+        let source = self.source;
+        let engine = self.engine;
+        let in_flight = self.in_flight.clone();
+        let finalized = self.finalized.clone();
+        let mut event = self.event(segment_id, start_sample, samples.len());
+        tokio::spawn(async move {
+            let result = api::transcribe(&app, audio_base64, engine)
+                .await
+                .and_then(|response| response.into_transcription());
+            in_flight.store(false, Ordering::Release);
+
+            // The final transcript already superseded this one
+            if finalized.load(Ordering::Acquire) >= segment_id {
+                return;
+            }
+            match result {
+                Ok(text) => {
+                    event.text = text;
+                    source.emit(&app, "transcript-partial", event);
+                }
+                Err(e) => eprintln!("Partial transcription failed: {}", e),
+            }
+        });
+    }
+
+    /// Transcribes the finished segment and emits `transcript-final`, errors included.
+    pub fn finish(&mut self, segment_id: u64, start_sample: u64, samples: &[f32]) {
+        self.finalized.store(segment_id, Ordering::Release);
+        self.last_partial_len = 0;
+
+        let app = self.app.clone();
+        let source = self.source;
+        let engine = self.engine;
+        let mut event = self.event(segment_id, start_sample, samples.len());
+        let audio_base64 = samples_to_wav_b64(self.sample_rate, samples);
+        tokio::spawn(async move {
+            let result = match audio_base64 {
+                Ok(audio_base64) => api::transcribe(&app, audio_base64, engine)
+                    .await
+                    .and_then(|response| response.into_transcription()),
+                Err(e) => Err(PluelyError::audio(format!("Failed to encode segment: {}", e))),
+            };
+            match result {
+                Ok(text) => event.text = text,
+                Err(e) => event.error = Some(e),
+            }
+            source.emit(&app, "transcript-final", event);
+        });
+    }
+}
@@ -0,0 +1,665 @@
+// Pluely voice activity detection, decides per analysis chunk whether it contains speech
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// Energy detector
+const VAD_SENSITIVITY_RMS: f32 = 0.004;  // RMS sensitivity for VAD
+const SPEECH_PEAK_THRESHOLD: f32 = 0.01;  // Peak threshold for VAD
+
+// Spectral detector
+const MIN_SPEECH_RMS: f32 = 0.002;  // Below this a chunk is silence whatever its spectrum
+const SPEECH_BAND_HZ: (f32, f32) = (250.0, 3400.0);  // Where most voice energy sits, down to the first formant of close vowels
+const LOW_CUT_HZ: f32 = 80.0;  // Ignore hum and DC
+const MIN_SPEECH_BAND_RATIO: f32 = 0.45;  // Share of energy inside the speech band
+const MAX_SPECTRAL_FLATNESS: f32 = 0.4;  // Noise and clicks are flat, voiced speech is peaky
+const PITCH_RANGE_HZ: (f32, f32) = (60.0, 500.0);  // Voice fundamentals, chimes and beeps sit far higher
+const PITCH_LOWPASS_HZ: f32 = 900.0;  // Keeps the fundamental and first formant for the pitch estimate
+const PITCH_PEAK_RATIO: f32 = 0.8;  // Earliest autocorrelation peak this close to the best one is the period
+const ONSET_CHUNKS: usize = 3;  // ~70ms of voiced chunks before speech starts, rejects clicks
+const MODULATION_WINDOW: usize = 24;  // ~0.5s of chunk energies
+const MIN_ENERGY_MODULATION: f32 = 0.5;  // Std dev of ln energy, syllables vary far more than music
+
+// Adaptive detector
+const CALIBRATION_MS: u64 = 3000;  // Ambient audio measured after the capture starts
+const CALIBRATION_PERCENTILE: f32 = 0.2;  // Low percentile so a stray sound doesn't raise the floor
+const RMS_MARGIN: f32 = 3.16;  // Speech RMS at least +10 dB over the floor
+const PEAK_MARGIN: f32 = 10.0;  // Speech peaks at least +20 dB over the floor
+const MIN_NOISE_FLOOR: f32 = 0.0001;  // -80 dBFS, digital silence would make any sound speech
+const FLOOR_FALL_RATE: f32 = 0.1;  // Follow a quieter room quickly
+const FLOOR_RISE_RATE: f32 = 0.005;  // but a louder one slowly, so speech doesn't raise the floor
+const RECALIBRATION_DB: f32 = 6.0;  // Floor drift that is reported again
+
+// Detector selected when the capture starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VadKind {
+    // RMS and peak thresholds, cheap but triggers on any loud sound
+    #[default]
+    Energy,
+    // Speech band energy, spectral flatness, voice pitch and syllable-rate modulation
+    Spectral,
+    // Energy thresholds relative to the measured noise floor
+    Adaptive,
+}
+
+// Payload of `vad-calibrated`
+#[derive(Debug, Clone, Serialize)]
+pub struct NoiseFloor {
+    noise_rms: f32,
+    noise_dbfs: f32,
+    rms_threshold: f32,
+    peak_threshold: f32,
+    // False for the initial calibration, true when the tracked floor drifted since
+    recalibrated: bool,
+}
+
+pub trait VoiceActivityDetector: Send {
+    fn name(&self) -> &'static str;
+
+    // Whether the chunk contains speech, chunks arrive in order
+    fn is_speech(&mut self, chunk: &[f32]) -> bool;
+
+    // New noise floor measurement since the last call, for detectors that calibrate
+    fn take_calibration(&mut self) -> Option<NoiseFloor> {
+        None
+    }
+}
+
+pub fn vad_for(kind: VadKind, sample_rate: u32) -> Box<dyn VoiceActivityDetector> {
+    match kind {
+        VadKind::Energy => Box::new(EnergyVad),
+        VadKind::Spectral => Box::new(SpectralVad::new(sample_rate)),
+        VadKind::Adaptive => Box::new(AdaptiveVad::new(sample_rate)),
+    }
+}
+
+// Process a chunk for Pluely AI Speech Detection (RMS and peak calculation)
+pub fn process_chunk(mono_chunk: &[f32]) -> (f32, f32) {
+    let mut sumsq = 0.0f32;
+    let mut peak = 0.0f32;
+    for &v in mono_chunk {
+        let a = v.abs();
+        peak = peak.max(a);
+        sumsq += v * v;
+    }
+    let rms = (sumsq / mono_chunk.len().max(1) as f32).sqrt();
+    (rms, peak)
+}
+
+pub struct EnergyVad;
+
+impl VoiceActivityDetector for EnergyVad {
+    fn name(&self) -> &'static str {
+        "energy"
+    }
+
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        let (rms, peak) = process_chunk(chunk);
+        rms > VAD_SENSITIVITY_RMS || peak > SPEECH_PEAK_THRESHOLD
+    }
+}
+
+pub struct SpectralVad {
+    sample_rate: u32,
+    // Consecutive chunks that looked like voice
+    voiced_chunks: usize,
+    active: bool,
+    // Started before there was history to measure modulation, checked once there is
+    unconfirmed: bool,
+    // ln energy of recent chunks, to tell speech from steady music
+    energies: VecDeque<f32>,
+}
+
+impl SpectralVad {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            voiced_chunks: 0,
+            active: false,
+            unconfirmed: false,
+            energies: VecDeque::with_capacity(MODULATION_WINDOW),
+        }
+    }
+
+    fn is_voiced(&self, chunk: &[f32]) -> bool {
+        let (rms, _) = process_chunk(chunk);
+        if rms < MIN_SPEECH_RMS || self.sample_rate == 0 {
+            return false;
+        }
+
+        let spectrum = power_spectrum(chunk);
+        let bin_hz = self.sample_rate as f32 / (spectrum.len() * 2) as f32;
+        let bin = |hz: f32| ((hz / bin_hz) as usize).min(spectrum.len());
+
+        let total: f32 = spectrum[bin(LOW_CUT_HZ)..].iter().sum();
+        let band = &spectrum[bin(SPEECH_BAND_HZ.0)..bin(SPEECH_BAND_HZ.1)];
+        if total <= f32::EPSILON || band.is_empty() {
+            return false;
+        }
+
+        let band_energy: f32 = band.iter().sum();
+        band_energy / total >= MIN_SPEECH_BAND_RATIO
+            && spectral_flatness(band) <= MAX_SPECTRAL_FLATNESS
+            && pitch_hz(chunk, self.sample_rate)
+                .is_some_and(|pitch| (PITCH_RANGE_HZ.0..=PITCH_RANGE_HZ.1).contains(&pitch))
+    }
+
+    fn energy_modulation(&self) -> f32 {
+        let n = self.energies.len() as f32;
+        let mean = self.energies.iter().sum::<f32>() / n;
+        (self.energies.iter().map(|e| (e - mean).powi(2)).sum::<f32>() / n).sqrt()
+    }
+}
+
+impl VoiceActivityDetector for SpectralVad {
+    fn name(&self) -> &'static str {
+        "spectral"
+    }
+
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        let (rms, _) = process_chunk(chunk);
+        if self.energies.len() == MODULATION_WINDOW {
+            self.energies.pop_front();
+        }
+        self.energies.push_back((rms * rms + 1e-10).ln());
+
+        if !self.is_voiced(chunk) {
+            self.voiced_chunks = 0;
+            self.active = false;
+            self.unconfirmed = false;
+            return false;
+        }
+        self.voiced_chunks += 1;
+
+        // Onset needs a run of voiced chunks and, once there is enough history,
+        // the energy swings of syllables. Ongoing speech only needs to stay voiced.
+        let full = self.energies.len() == MODULATION_WINDOW;
+        if !self.active {
+            let modulated = !full || self.energy_modulation() >= MIN_ENERGY_MODULATION;
+            self.active = self.voiced_chunks >= ONSET_CHUNKS && modulated;
+            self.unconfirmed = self.active && !full;
+        } else if self.unconfirmed && full {
+            // Music playing when the capture started only looks like speech until now
+            self.unconfirmed = false;
+            self.active = self.energy_modulation() >= MIN_ENERGY_MODULATION;
+        }
+        self.active
+    }
+}
+
+pub struct AdaptiveVad {
+    calibration_samples: u64,
+    samples_seen: u64,
+    // Chunk RMS values while calibrating
+    ambient: Vec<f32>,
+    noise_floor: Option<f32>,
+    reported_floor: Option<f32>,
+    pending: Option<NoiseFloor>,
+}
+
+impl AdaptiveVad {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            calibration_samples: sample_rate as u64 * CALIBRATION_MS / 1000,
+            samples_seen: 0,
+            ambient: Vec::new(),
+            noise_floor: None,
+            reported_floor: None,
+            pending: None,
+        }
+    }
+
+    fn report(&mut self, floor: f32) {
+        let recalibrated = match self.reported_floor {
+            None => false,
+            Some(reported) if (to_dbfs(floor) - to_dbfs(reported)).abs() >= RECALIBRATION_DB => true,
+            Some(_) => return,
+        };
+
+        self.reported_floor = Some(floor);
+        self.pending = Some(NoiseFloor {
+            noise_rms: floor,
+            noise_dbfs: to_dbfs(floor),
+            rms_threshold: floor * RMS_MARGIN,
+            peak_threshold: floor * PEAK_MARGIN,
+            recalibrated,
+        });
+    }
+}
+
+impl VoiceActivityDetector for AdaptiveVad {
+    fn name(&self) -> &'static str {
+        "adaptive"
+    }
+
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        let (rms, peak) = process_chunk(chunk);
+        self.samples_seen += chunk.len() as u64;
+
+        let Some(floor) = self.noise_floor else {
+            // Everything heard while calibrating counts as ambient
+            self.ambient.push(rms);
+            if self.samples_seen >= self.calibration_samples {
+                self.ambient.sort_by(f32::total_cmp);
+                let index = ((self.ambient.len() - 1) as f32 * CALIBRATION_PERCENTILE) as usize;
+                let floor = self.ambient[index].max(MIN_NOISE_FLOOR);
+                self.ambient = Vec::new();
+                self.noise_floor = Some(floor);
+                self.report(floor);
+            }
+            return false;
+        };
+
+        let is_speech = rms > floor * RMS_MARGIN || peak > floor * PEAK_MARGIN;
+        if !is_speech {
+            let rate = if rms < floor { FLOOR_FALL_RATE } else { FLOOR_RISE_RATE };
+            let floor = (floor + (rms - floor) * rate).max(MIN_NOISE_FLOOR);
+            self.noise_floor = Some(floor);
+            self.report(floor);
+        }
+        is_speech
+    }
+
+    fn take_calibration(&mut self) -> Option<NoiseFloor> {
+        self.pending.take()
+    }
+}
+
+pub fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-10).log10()
+}
+
+// Hann-windowed power spectrum of the chunk, zero padded to a power of two
+fn power_spectrum(chunk: &[f32]) -> Vec<f32> {
+    let n = chunk.len().next_power_of_two();
+    let mut re = vec![0.0f32; n];
+    let mut im = vec![0.0f32; n];
+    for (i, &v) in chunk.iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / chunk.len() as f32).cos();
+        re[i] = v * window;
+    }
+
+    fft(&mut re, &mut im);
+    (0..n / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect()
+}
+
+// In-place iterative radix-2 FFT
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// Geometric over arithmetic mean, 1.0 for white noise and near 0 for a pure tone
+fn spectral_flatness(power: &[f32]) -> f32 {
+    let n = power.len() as f32;
+    let log_mean = power.iter().map(|p| (p + 1e-12).ln()).sum::<f32>() / n;
+    let mean = power.iter().sum::<f32>() / n;
+    if mean <= f32::EPSILON {
+        return 1.0;
+    }
+    log_mean.exp() / mean
+}
+
+// Fundamental frequency from the normalized autocorrelation, None when no period fits in the chunk
+fn pitch_hz(chunk: &[f32], sample_rate: u32) -> Option<f32> {
+    let max_lag = ((sample_rate as f32 / PITCH_RANGE_HZ.0) as usize).min(chunk.len() * 3 / 4);
+    if max_lag < 3 {
+        return None;
+    }
+
+    // Two one-pole lowpasses, so a strong upper formant can't pass for the period
+    let alpha = 1.0 - (-2.0 * PI * PITCH_LOWPASS_HZ / sample_rate as f32).exp();
+    let (mut first, mut second) = (0.0f32, 0.0f32);
+    let smoothed: Vec<f32> = chunk
+        .iter()
+        .map(|&x| {
+            first += alpha * (x - first);
+            second += alpha * (first - second);
+            second
+        })
+        .collect();
+
+    let correlation: Vec<f32> = (0..=max_lag)
+        .map(|lag| {
+            let (head, tail) = (&smoothed[..smoothed.len() - lag], &smoothed[lag..]);
+            let product: f32 = head.iter().zip(tail).map(|(a, b)| a * b).sum();
+            let energy: f32 = head.iter().map(|a| a * a).sum::<f32>() * tail.iter().map(|b| b * b).sum::<f32>();
+            product / energy.sqrt().max(1e-12)
+        })
+        .collect();
+
+    let best = correlation[2..].iter().copied().fold(0.0, f32::max);
+    (2..max_lag)
+        .find(|&lag| {
+            correlation[lag] >= correlation[lag - 1]
+                && correlation[lag] >= correlation[lag + 1]
+                && correlation[lag] >= best * PITCH_PEAK_RATIO
+        })
+        .map(|lag| sample_rate as f32 / lag as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+    const HOP: usize = 368;  // 23ms, the default hop at 16 kHz
+
+    // Generated signals for the cases the fixtures don't isolate
+
+    // Harmonics of a 140 Hz voice shaped by three formants, in 4 Hz syllables with pauses between
+    fn speech(seconds: f32, level: f32) -> Vec<f32> {
+        let len = (SAMPLE_RATE as f32 * seconds) as usize;
+        let formants = [(700.0, 150.0), (1200.0, 200.0), (2500.0, 300.0)];
+        let harmonics: Vec<(f32, f32)> = (1..=24)
+            .map(|k| {
+                let hz = 140.0 * k as f32;
+                let gain: f32 = formants
+                    .iter()
+                    .map(|(center, width)| (-((hz - center) / width).powi(2)).exp())
+                    .sum();
+                (hz, gain)
+            })
+            .collect();
+        let norm: f32 = harmonics.iter().map(|(_, gain)| gain * gain).sum::<f32>().sqrt();
+
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let syllable = (PI * 4.0 * t).sin().powi(2);
+                let voice: f32 = harmonics
+                    .iter()
+                    .map(|(hz, gain)| gain * (2.0 * PI * hz * t).sin())
+                    .sum();
+                level * syllable * voice / norm * 2.0f32.sqrt()
+            })
+            .collect()
+    }
+
+    fn noise(seconds: f32, rms: f32, seed: u64) -> Vec<f32> {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let len = (SAMPLE_RATE as f32 * seconds) as usize;
+        // Uniform noise in [-a, a] has an RMS of a / sqrt(3)
+        let amplitude = rms * 3.0f32.sqrt();
+        (0..len).map(|_| (rng.f32() * 2.0 - 1.0) * amplitude).collect()
+    }
+
+    // A sustained chord, steady like music rather than speech
+    fn chord(seconds: f32, level: f32) -> Vec<f32> {
+        let len = (SAMPLE_RATE as f32 * seconds) as usize;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let notes: f32 = [440.0, 554.4, 659.3].iter().map(|hz| (2.0 * PI * hz * t).sin()).sum();
+                notes * level / 3.0
+            })
+            .collect()
+    }
+
+    fn detect(vad: &mut dyn VoiceActivityDetector, audio: &[f32]) -> Vec<bool> {
+        audio.chunks_exact(HOP).map(|chunk| vad.is_speech(chunk)).collect()
+    }
+
+    fn share(detections: &[bool]) -> f32 {
+        detections.iter().filter(|&&d| d).count() as f32 / detections.len().max(1) as f32
+    }
+
+    // 16 kHz mono clips from tests/fixtures/vad, see the README there
+    fn fixture(name: &str) -> Vec<f32> {
+        let path = format!("{}/tests/fixtures/vad/{}.wav", env!("CARGO_MANIFEST_DIR"), name);
+        let mut reader = hound::WavReader::open(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE, "{}", path);
+        assert_eq!(reader.spec().channels, 1, "{}", path);
+        reader.samples::<i16>().map(|s| s.unwrap() as f32 / 32768.0).collect()
+    }
+
+    // Runs of chunks that are loud enough to be words, as (first chunk, length)
+    fn loud_runs(audio: &[f32]) -> Vec<(usize, usize)> {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for (i, loud) in detect(&mut EnergyVad, audio).into_iter().chain([false]).enumerate() {
+            match (loud, start) {
+                (true, None) => start = Some(i),
+                (false, Some(first)) => {
+                    runs.push((first, i - first));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn energy_vad_fires_on_every_fixture() {
+        // The problem the other detectors solve: anything loud is speech
+        for name in ["speech", "music", "keyboard", "notification"] {
+            let detections = detect(&mut EnergyVad, &fixture(name));
+            assert!(detections.contains(&true), "{}: nothing detected", name);
+        }
+    }
+
+    #[test]
+    fn spectral_vad_finds_every_word_in_speech_fixture() {
+        let audio = fixture("speech");
+        let detections = detect(&mut SpectralVad::new(SAMPLE_RATE), &audio);
+
+        let words = loud_runs(&audio);
+        assert!(words.len() >= 5, "{} words", words.len());
+        for (first, len) in words {
+            let word = &detections[first..first + len];
+            assert!(share(word) >= 0.3, "word at chunk {}: {:.0}% detected", first, share(word) * 100.0);
+        }
+        let silence = detections.iter().zip(detect(&mut EnergyVad, &audio)).filter(|(_, loud)| !loud);
+        assert!(silence.clone().all(|(&d, _)| !d), "speech detected in a pause");
+    }
+
+    #[test]
+    fn spectral_vad_rejects_keyboard_and_notification_fixtures() {
+        for name in ["keyboard", "notification"] {
+            let detections = detect(&mut SpectralVad::new(SAMPLE_RATE), &fixture(name));
+            assert_eq!(share(&detections), 0.0, "{}", name);
+        }
+    }
+
+    #[test]
+    fn spectral_vad_rejects_music_fixture() {
+        let detections = detect(&mut SpectralVad::new(SAMPLE_RATE), &fixture("music"));
+        // Music from the first chunk passes until there is history to measure modulation against
+        assert!(!detections[MODULATION_WINDOW..].contains(&true));
+    }
+
+    #[test]
+    fn synthetic_fixtures_have_the_intended_levels() {
+        let (speech_rms, _) = process_chunk(&speech(1.0, 0.1));
+        // The syllable envelope averages to half the power
+        assert!((speech_rms - 0.1 / 2.0f32.sqrt()).abs() < 0.01, "speech rms {}", speech_rms);
+        let (noise_rms, _) = process_chunk(&noise(1.0, 0.01, 1));
+        assert!((noise_rms - 0.01).abs() < 0.001, "noise rms {}", noise_rms);
+    }
+
+    #[test]
+    fn energy_vad_follows_level() {
+        let mut vad = EnergyVad;
+        assert_eq!(share(&detect(&mut vad, &vec![0.0; 16000])), 0.0);
+        assert_eq!(share(&detect(&mut vad, &noise(1.0, 0.001, 1))), 0.0);
+        let speech_share = share(&detect(&mut vad, &speech(2.0, 0.1)));
+        assert!(speech_share > 0.7, "speech detected in {:.0}% of chunks", speech_share * 100.0);
+        // Any loud sound counts, which is what the other detectors are for
+        assert_eq!(share(&detect(&mut vad, &noise(1.0, 0.05, 2))), 1.0);
+    }
+
+    #[test]
+    fn spectral_vad_detects_speech() {
+        let mut vad = SpectralVad::new(SAMPLE_RATE);
+        let detections = detect(&mut vad, &speech(3.0, 0.1));
+        let speech_share = share(&detections);
+        assert!(speech_share > 0.4, "speech detected in {:.0}% of chunks", speech_share * 100.0);
+
+        // Each syllable peak is found, the pauses between them are not speech
+        let syllable_chunks = SAMPLE_RATE as usize / 4 / HOP;
+        for (i, syllable) in detections.chunks(syllable_chunks).enumerate() {
+            assert!(syllable.contains(&true), "syllable {} missed", i);
+        }
+        assert!(!detections[0], "onset needs a run of voiced chunks");
+    }
+
+    #[test]
+    fn spectral_vad_rejects_noise_and_clicks() {
+        let mut vad = SpectralVad::new(SAMPLE_RATE);
+        assert_eq!(share(&detect(&mut vad, &noise(3.0, 0.05, 3))), 0.0);
+
+        let mut click = vec![0.0; HOP];
+        click[HOP / 2] = 0.9;
+        let mut vad = SpectralVad::new(SAMPLE_RATE);
+        assert!((0..5).all(|_| !vad.is_speech(&click)));
+
+        assert_eq!(share(&detect(&mut vad, &vec![0.0; 16000])), 0.0);
+    }
+
+    #[test]
+    fn spectral_vad_uses_modulation_to_reject_steady_music() {
+        // Noise at the chord's level fills the history without voicing or an energy jump
+        let chord_rms = 0.1 / 6.0f32.sqrt();
+        let mut vad = SpectralVad::new(SAMPLE_RATE);
+        detect(&mut vad, &noise(1.0, chord_rms, 8));
+        assert_eq!(share(&detect(&mut vad, &chord(3.0, 0.1))), 0.0);
+
+        // Syllables after the same noise do swing enough
+        let mut vad = SpectralVad::new(SAMPLE_RATE);
+        detect(&mut vad, &noise(1.0, chord_rms, 8));
+        let speech_share = share(&detect(&mut vad, &speech(2.0, 0.1)));
+        assert!(speech_share > 0.4, "speech detected in {:.0}% of chunks", speech_share * 100.0);
+    }
+
+    // The calibration period plus a chunk, as whole chunks fall short of it otherwise
+    fn calibration_seconds() -> f32 {
+        CALIBRATION_MS as f32 / 1000.0 + HOP as f32 / SAMPLE_RATE as f32
+    }
+
+    #[test]
+    fn adaptive_vad_calibrates_then_detects_above_floor() {
+        let mut vad = AdaptiveVad::new(SAMPLE_RATE);
+        let ambient = noise(calibration_seconds(), 0.003, 4);
+        assert_eq!(share(&detect(&mut vad, &ambient)), 0.0);
+
+        let calibration = vad.take_calibration().expect("calibrated after the ambient period");
+        assert!(!calibration.recalibrated);
+        assert!((calibration.noise_dbfs - to_dbfs(0.003)).abs() < 1.5, "floor {} dBFS", calibration.noise_dbfs);
+
+        let speech_share = share(&detect(&mut vad, &speech(2.0, 0.1)));
+        assert!(speech_share > 0.7, "speech detected in {:.0}% of chunks", speech_share * 100.0);
+        assert_eq!(share(&detect(&mut vad, &noise(2.0, 0.003, 5))), 0.0);
+    }
+
+    #[test]
+    fn adaptive_vad_follows_a_quieter_room() {
+        let mut vad = AdaptiveVad::new(SAMPLE_RATE);
+        detect(&mut vad, &noise(calibration_seconds(), 0.01, 6));
+        vad.take_calibration();
+
+        detect(&mut vad, &noise(2.0, 0.001, 7));
+        let recalibration = vad.take_calibration().expect("floor drift reported");
+        assert!(recalibration.recalibrated);
+
+        // Quieter than the first room's thresholds, still speech in this one
+        let speech_share = share(&detect(&mut vad, &speech(1.0, 0.02)));
+        assert!(speech_share > 0.7, "speech detected in {:.0}% of chunks", speech_share * 100.0);
+    }
+
+    #[test]
+    fn adaptive_vad_finds_speech_fixture_over_room_noise() {
+        let room = 0.003;
+        let mut vad = AdaptiveVad::new(SAMPLE_RATE);
+        detect(&mut vad, &noise(calibration_seconds(), room, 10));
+        vad.take_calibration();
+
+        let audio: Vec<f32> = fixture("speech")
+            .iter()
+            .zip(noise(10.0, room, 11))
+            .map(|(speech, noise)| speech + noise)
+            .collect();
+        let detections = detect(&mut vad, &audio);
+        for (first, len) in loud_runs(&fixture("speech")) {
+            let word = &detections[first..first + len];
+            assert!(share(word) >= 0.5, "word at chunk {}: {:.0}% detected", first, share(word) * 100.0);
+        }
+        // The room noise alone, e.g. the pause before the first word
+        assert!(!detections[..5].contains(&true));
+    }
+
+    fn dft(input: &[f32]) -> (Vec<f64>, Vec<f64>) {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input.iter().enumerate().fold((0.0, 0.0), |(re, im), (t, &x)| {
+                    let angle = -2.0 * std::f64::consts::PI * (k * t) as f64 / n as f64;
+                    (re + x as f64 * angle.cos(), im + x as f64 * angle.sin())
+                })
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn fft_matches_dft() {
+        for n in [1, 2, 8, 64, 512] {
+            let input = noise(n as f32 / SAMPLE_RATE as f32, 0.5, n as u64);
+            let (expected_re, expected_im) = dft(&input);
+
+            let mut re = input.clone();
+            let mut im = vec![0.0; n];
+            fft(&mut re, &mut im);
+
+            // f32 rounding grows with the number of stages
+            let tolerance = 1e-3 * (n as f64).sqrt();
+            for k in 0..n {
+                let error = (re[k] as f64 - expected_re[k]).hypot(im[k] as f64 - expected_im[k]);
+                assert!(error < tolerance, "n={} bin {}: error {}", n, k, error);
+            }
+        }
+    }
+
+    #[test]
+    fn power_spectrum_peaks_at_tone_bin() {
+        let chunk: Vec<f32> = (0..HOP)
+            .map(|i| (2.0 * PI * 1000.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let spectrum = power_spectrum(&chunk);
+        assert_eq!(spectrum.len(), 256);
+
+        let peak = (0..spectrum.len()).max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b])).unwrap();
+        let bin_hz = SAMPLE_RATE as f32 / 512.0;
+        assert!((peak as f32 * bin_hz - 1000.0).abs() <= bin_hz, "peak at {} Hz", peak as f32 * bin_hz);
+    }
+}
@@ -2,13 +2,26 @@
 use anyhow::Result;
 use futures_util::Stream;
 use std::collections::VecDeque;
-use std::sync::{mpsc, Arc, Mutex};
-use std::task::{Poll, Waker};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::task::Poll;
 use std::thread;
-use wasapi::{get_default_device, Direction, SampleType, StreamMode, WaveFormat};
-use std::time::Duration;
+use wasapi::{
+    get_default_device, AudioCaptureClient, AudioClient, Direction, Handle, SampleType, StreamMode, WaveFormat,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tracing::error;
 
+use super::frames::{frame_channel, BufferStats, FrameReceiver, FrameSender};
+use super::{panic_message, reopen_with_backoff, CaptureEvent, CaptureEvents, CaptureStartError};
+
+const SAMPLE_RATE: u32 = 44100;
+const EVENT_TIMEOUT_MS: u32 = 500;
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);  // Default output polled this often
+const MAX_READ_ERRORS: u32 = 5;  // Consecutive failed reads before the device is reopened
+
 pub struct SpeakerInput {}
 
 impl SpeakerInput {
@@ -17,150 +30,208 @@ impl SpeakerInput {
     }
 
     // Starts the audio stream
-    pub fn stream(self) -> SpeakerStream {
-        let sample_queue = Arc::new(Mutex::new(VecDeque::new()));
-        let waker_state = Arc::new(Mutex::new(WakerState {
-            waker: None,
-            has_data: false,
-            shutdown: false,
-        }));
+    pub fn stream(self) -> Result<SpeakerStream, CaptureStartError> {
+        let (mut sender, frames) = frame_channel(SAMPLE_RATE);
         let (init_tx, init_rx) = mpsc::channel();
+        let (events_tx, events_rx) = unbounded_channel();
 
-        let queue_clone = sample_queue.clone();
-        let waker_clone = waker_state.clone();
-
+        // Dropping the sender at the end ends the stream when the capture gave up on its own
         let capture_thread = thread::spawn(move || {
-            if let Err(e) = SpeakerStream::capture_audio_loop(queue_clone, waker_clone, init_tx) {
-                error!("Pluely Audio capture loop failed: {}", e);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                SpeakerStream::capture_audio_loop(&mut sender, init_tx, events_tx.clone())
+            }));
+            let stopped = match result {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(panic) => Some(panic_message(&*panic)),
+            };
+            if let Some(reason) = stopped {
+                error!("Pluely Audio capture loop failed: {}", reason);
+                let _ = events_tx.send(CaptureEvent::stopped(reason));
             }
         });
 
-        if let Ok(Err(e)) = init_rx.recv_timeout(Duration::from_secs(5)) {
-            error!("Pluely Audio initialization failed: {}", e);
+        let started = match init_rx.recv_timeout(INIT_TIMEOUT) {
+            Ok(started) => started,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // The thread may be stuck in WASAPI or COM init, so it is left to exit
+                // on its own once it sees the closed channel rather than joined here
+                frames.close();
+                return Err(CaptureStartError::Timeout);
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(CaptureStartError::backend("Capture thread exited during startup"))
+            }
+        };
+        if let Err(e) = started {
+            if let Err(e) = capture_thread.join() {
+                error!("Failed to join capture thread: {:?}", e);
+            }
+            return Err(e);
         }
 
-        SpeakerStream {
-            sample_queue,
-            waker_state,
+        Ok(SpeakerStream {
+            frames,
             capture_thread: Some(capture_thread),
-        }
+            events: Some(events_rx),
+        })
     }
 }
 
-struct WakerState {
-    waker: Option<Waker>,
-    has_data: bool,
-    shutdown: bool,
-}
-
 pub struct SpeakerStream {
-    sample_queue: Arc<Mutex<VecDeque<f32>>>,
-    waker_state: Arc<Mutex<WakerState>>,
+    frames: FrameReceiver,
     capture_thread: Option<thread::JoinHandle<()>>,
+    events: Option<CaptureEvents>,
 }
 
-impl SpeakerStream {
-    pub fn sample_rate(&self) -> u32 {
-        44100
-    }
-
-    fn capture_audio_loop(
-        sample_queue: Arc<Mutex<VecDeque<f32>>>,
-        waker_state: Arc<Mutex<WakerState>>,
-        init_tx: mpsc::Sender<Result<()>>,
-    ) -> Result<()> {
-        let init_result = (|| -> Result<_> {
-            let device = get_default_device(&Direction::Render)?;
-            let mut audio_client = device.get_iaudioclient()?;
+// Loopback capture of one render device
+struct Capture {
+    h_event: Handle,
+    capture_client: AudioCaptureClient,
+    audio_client: AudioClient,
+    device_id: String,
+    device_name: String,
+}
 
-            let desired_format = WaveFormat::new(32, 32, &SampleType::Float, 44100, 1, None);
+impl Capture {
+    fn open_default() -> Result<Self, CaptureStartError> {
+        let device = get_default_device(&Direction::Render)
+            .map_err(CaptureStartError::no_device)?;
+        let device_id = device.get_id().map_err(CaptureStartError::backend)?;
+        let device_name = device.get_friendlyname().unwrap_or_else(|_| device_id.clone());
+        let mut audio_client = device.get_iaudioclient().map_err(CaptureStartError::backend)?;
 
-            let (_def_time, min_time) = audio_client.get_device_period()?;
+        let desired_format = WaveFormat::new(32, 32, &SampleType::Float, SAMPLE_RATE as usize, 1, None);
 
-            let mode = StreamMode::EventsShared {
-                autoconvert: true,
-                buffer_duration_hns: min_time,
-            };
+        let (_def_time, min_time) = audio_client.get_device_period().map_err(CaptureStartError::backend)?;
 
-            audio_client.initialize_client(&desired_format, &Direction::Capture, &mode)?;
+        let mode = StreamMode::EventsShared {
+            autoconvert: true,
+            buffer_duration_hns: min_time,
+        };
 
-            let h_event = audio_client.set_get_eventhandle()?;
-            let render_client = audio_client.get_audiocaptureclient()?;
+        audio_client
+            .initialize_client(&desired_format, &Direction::Capture, &mode)
+            .map_err(CaptureStartError::backend)?;
 
-            audio_client.start_stream()?;
+        let h_event = audio_client.set_get_eventhandle().map_err(CaptureStartError::backend)?;
+        let capture_client = audio_client.get_audiocaptureclient().map_err(CaptureStartError::backend)?;
 
-            Ok((h_event, render_client))
-        })();
+        audio_client.start_stream().map_err(CaptureStartError::backend)?;
 
-        match init_result {
-            Ok((h_event, render_client)) => {
-                let _ = init_tx.send(Ok(()));
+        Ok(Self {
+            h_event,
+            capture_client,
+            audio_client,
+            device_id,
+            device_name,
+        })
+    }
+}
 
-                loop {
-                    {
-                        let state = waker_state.lock().unwrap();
-                        if state.shutdown {
-                            break;
-                        }
-                    }
+fn default_device_id() -> Result<String> {
+    Ok(get_default_device(&Direction::Render)?.get_id()?)
+}
 
-                    if h_event.wait_for_event(3000).is_err() {
-                        error!("Pluely timeout error, stopping capture");
-                        break;
-                    }
+impl SpeakerStream {
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
 
-                    let mut temp_queue = VecDeque::new();
-                    if let Err(e) = render_client.read_from_device_to_deque(&mut temp_queue) {
-                        error!("Pluely Failed to read audio data: {}", e);
-                        continue;
-                    }
+    pub fn take_events(&mut self) -> Option<CaptureEvents> {
+        self.events.take()
+    }
 
-                    if temp_queue.is_empty() {
-                        continue;
-                    }
+    pub fn stats(&self) -> Arc<BufferStats> {
+        self.frames.stats()
+    }
 
-                    let mut samples = Vec::new();
-                    while temp_queue.len() >= 4 {
-                        let bytes = [
-                            temp_queue.pop_front().unwrap(),
-                            temp_queue.pop_front().unwrap(),
-                            temp_queue.pop_front().unwrap(),
-                            temp_queue.pop_front().unwrap(),
-                        ];
-                        let sample = f32::from_le_bytes(bytes);
-                        samples.push(sample);
-                    }
+    fn capture_audio_loop(
+        frames: &mut FrameSender,
+        init_tx: mpsc::Sender<Result<(), CaptureStartError>>,
+        events: UnboundedSender<CaptureEvent>,
+    ) -> Result<()> {
+        let mut capture = match Capture::open_default() {
+            Ok(capture) => {
+                let _ = init_tx.send(Ok(()));
+                capture
+            }
+            Err(e) => {
+                let _ = init_tx.send(Err(e));
+                return Ok(());
+            }
+        };
+
+        let reopen = || Capture::open_default().map_err(anyhow::Error::from);
+        let mut last_device_check = Instant::now();
+        let mut read_errors = 0;
+
+        while !frames.is_closed() {
+            // Loopback only signals while something plays, so a timeout is just silence
+            let signalled = capture.h_event.wait_for_event(EVENT_TIMEOUT_MS).is_ok();
+
+            // Follow the default output, e.g. when headphones are plugged in
+            if last_device_check.elapsed() >= DEVICE_CHECK_INTERVAL {
+                last_device_check = Instant::now();
+                match default_device_id() {
+                    Ok(id) if id != capture.device_id => match Capture::open_default() {
+                        Ok(reopened) => {
+                            let _ = capture.audio_client.stop_stream();
+                            capture = reopened;
+                            read_errors = 0;
+                            let _ = events.send(CaptureEvent::device_changed(&capture.device_name));
+                            continue;
+                        }
+                        Err(e) => error!("Pluely Failed to switch to the new default output: {}", e),
+                    },
+                    Ok(_) => {}
+                    Err(e) => error!("Pluely Failed to get the default output: {}", e),
+                }
+            }
 
-                    if !samples.is_empty() {
-                        {
-                            let mut queue = sample_queue.lock().unwrap();
-                            queue.extend(samples);
+            if !signalled {
+                continue;
+            }
 
-                            let len = queue.len();
-                            if len > 8192 {
-                                queue.drain(0..(len - 8192));
-                            }
-                        }
+            let mut temp_queue = VecDeque::new();
+            if let Err(e) = capture.capture_client.read_from_device_to_deque(&mut temp_queue) {
+                error!("Pluely Failed to read audio data: {}", e);
+                read_errors += 1;
+                if read_errors < MAX_READ_ERRORS {
+                    continue;
+                }
 
-                        {
-                            let mut state = waker_state.lock().unwrap();
-                            if !state.has_data {
-                                state.has_data = true;
-                                if let Some(waker) = state.waker.take() {
-                                    drop(state);
-                                    waker.wake();
-                                }
-                            }
-                        }
+                // The device was most likely invalidated, e.g. unplugged
+                let _ = events.send(CaptureEvent::error(&e, true));
+                match reopen_with_backoff(|| frames.is_closed(), reopen) {
+                    Ok(reopened) => {
+                        capture = reopened;
+                        read_errors = 0;
+                        let _ = events.send(CaptureEvent::device_changed(&capture.device_name));
+                        continue;
+                    }
+                    Err(_) if frames.is_closed() => return Ok(()),
+                    Err(e) => {
+                        let _ = events.send(CaptureEvent::error(&e, false));
+                        return Err(e);
                     }
                 }
             }
-            Err(e) => {
-                let _ = init_tx.send(Err(e));
-                return Ok(());
+            read_errors = 0;
+
+            if temp_queue.is_empty() {
+                continue;
             }
+
+            let samples: Vec<f32> = temp_queue
+                .make_contiguous()
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect();
+            frames.push(&samples);
         }
 
+        let _ = capture.audio_client.stop_stream();
         Ok(())
     }
 }
@@ -168,10 +239,7 @@ impl SpeakerStream {
 // Drops the audio stream
 impl Drop for SpeakerStream {
     fn drop(&mut self) {
-        {
-            let mut state = self.waker_state.lock().unwrap();
-            state.shutdown = true;
-        }
+        self.frames.close();
 
         if let Some(thread) = self.capture_thread.take() {
             if let Err(e) = thread.join() {
@@ -181,45 +249,15 @@ impl Drop for SpeakerStream {
     }
 }
 
-// Stream of f32 audio samples from the speaker
+// Stream of fixed-size frames of f32 audio samples from the speaker
 impl Stream for SpeakerStream {
-    type Item = f32;
+    type Item = Vec<f32>;
 
     // Polls the audio stream
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
+        mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        {
-            let state = self.waker_state.lock().unwrap();
-            if state.shutdown {
-                return Poll::Ready(None);
-            }
-        }
-
-        {
-            let mut queue = self.sample_queue.lock().unwrap();
-            if let Some(sample) = queue.pop_front() {
-                return Poll::Ready(Some(sample));
-            }
-        }
-
-        {
-            let mut state = self.waker_state.lock().unwrap();
-            if state.shutdown {
-                return Poll::Ready(None);
-            }
-            state.has_data = false;
-            state.waker = Some(cx.waker().clone());
-            drop(state);
-        }
-
-        {
-            let mut queue = self.sample_queue.lock().unwrap();
-            match queue.pop_front() {
-                Some(sample) => Poll::Ready(Some(sample)),
-                None => Poll::Pending,
-            }
-        }
+        std::pin::Pin::new(&mut self.frames).poll_next(cx)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,324 @@
+// Pluely Server-Sent Events decoder, shared by every streaming endpoint
+// Follows the WHATWG event stream format: https://html.spec.whatwg.org/multipage/server-sent-events.html
+use futures_util::{Stream, StreamExt};
+use std::ops::ControlFlow;
+
+use crate::error::PluelyError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    // Event type, "message" when the server didn't send an `event:` field
+    pub event: String,
+    // All `data:` lines of the event joined with '\n'
+    pub data: String,
+    // Last event ID seen on the stream
+    pub id: Option<String>,
+    // Reconnection time requested by the server, in milliseconds
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    pub fn is_error(&self) -> bool {
+        self.event == "error"
+    }
+}
+
+/// Incremental decoder, feed it raw bytes as they arrive from the network.
+/// Bytes are only decoded once a full line is available, so UTF-8 sequences
+/// split across chunks are handled correctly.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    // A chunk ended on '\r', a leading '\n' in the next chunk belongs to the same line break
+    skip_line_feed: bool,
+    started: bool,
+    event: String,
+    data: String,
+    has_data: bool,
+    last_event_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Feeds a chunk of bytes and returns every event completed by it
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        let mut bytes = bytes;
+
+        if self.skip_line_feed && !bytes.is_empty() {
+            self.skip_line_feed = false;
+            if bytes[0] == b'\n' {
+                bytes = &bytes[1..];
+            }
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.buffer.len() {
+            match self.buffer[i] {
+                b'\n' | b'\r' => {
+                    let line = self.buffer[start..i].to_vec();
+                    if self.buffer[i] == b'\r' {
+                        if i + 1 < self.buffer.len() {
+                            if self.buffer[i + 1] == b'\n' {
+                                i += 1;
+                            }
+                        } else {
+                            self.skip_line_feed = true;
+                        }
+                    }
+                    i += 1;
+                    start = i;
+                    if let Some(event) = self.process_line(&line) {
+                        events.push(event);
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        self.buffer.drain(..start);
+
+        events
+    }
+
+    /// Flushes a trailing line and event when the stream ends without a blank line.
+    /// The spec discards such events, but several providers close the stream right
+    /// after the last `data:` line, so we are lenient here.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Option<SseEvent> {
+        let mut line = String::from_utf8_lossy(line).into_owned();
+
+        // Strip the byte order mark from the very first line
+        if !self.started {
+            self.started = true;
+            if let Some(stripped) = line.strip_prefix('\u{feff}') {
+                line = stripped.to_string();
+            }
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comment, used by servers as keep-alive
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            // IDs containing NULL are ignored per spec
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse::<u64>() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.event);
+        if !self.has_data {
+            return None;
+        }
+        self.has_data = false;
+
+        Some(SseEvent {
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data: std::mem::take(&mut self.data),
+            id: self.last_event_id.clone(),
+            retry: self.retry,
+        })
+    }
+}
+
+/// Drives a byte stream through the decoder and calls `on_event` for every event.
+/// Returning `ControlFlow::Break` from `on_event` stops reading the stream.
+/// `event: error` frames are turned into an error.
+pub async fn for_each_event<S, B, E, F>(stream: S, mut on_event: F) -> Result<(), PluelyError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Into<PluelyError>,
+    F: FnMut(SseEvent) -> Result<ControlFlow<()>, PluelyError>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut decoder = SseDecoder::new();
+
+    while let Some(chunk) = stream.next().await {
+        let bytes = chunk.map_err(|e| e.into().context("Stream error"))?;
+        for event in decoder.feed(bytes.as_ref()) {
+            if event.is_error() {
+                return Err(stream_error(&event));
+            }
+            if on_event(event)?.is_break() {
+                return Ok(());
+            }
+        }
+    }
+
+    if let Some(event) = decoder.finish() {
+        if event.is_error() {
+            return Err(stream_error(&event));
+        }
+        let _ = on_event(event)?;
+    }
+    Ok(())
+}
+
+// `event: error` frames may or may not carry JSON
+fn stream_error(event: &SseEvent) -> PluelyError {
+    PluelyError::from_stream_error(&event.data)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events: Vec<SseEvent> = chunks.iter().flat_map(|chunk| decoder.feed(chunk)).collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.data.as_str()).collect()
+    }
+
+    #[test]
+    fn splits_on_every_line_ending() {
+        let events = decode(&[b"data: lf\n\ndata: crlf\r\n\r\ndata: cr\r\rdata: mixed\r\n\n"]);
+        assert_eq!(data(&events), ["lf", "crlf", "cr", "mixed"]);
+        assert!(events.iter().all(|event| event.event == "message"));
+    }
+
+    #[test]
+    fn crlf_split_between_chunks_is_one_line_break() {
+        // A lone '\n' after the '\r' would otherwise end the event early
+        let events = decode(&[b"data: a\r", b"\ndata: b\r", b"\n\r", b"\n"]);
+        assert_eq!(data(&events), ["a\nb"]);
+    }
+
+    #[test]
+    fn event_split_across_chunks() {
+        let events = decode(&[b"eve", b"nt: delta\nda", b"ta: {\"text\"", b": \"hi\"}\n", b"\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "delta");
+        assert_eq!(events[0].data, r#"{"text": "hi"}"#);
+    }
+
+    #[test]
+    fn utf8_codepoint_split_across_chunks() {
+        let bytes = "data: héllo 👋\n\n".as_bytes();
+        // Split inside both the two-byte 'é' and the four-byte emoji
+        let e = 8;
+        let emoji = bytes.len() - 4;
+        let events = decode(&[&bytes[..e], &bytes[e..emoji], &bytes[emoji..]]);
+        assert_eq!(data(&events), ["héllo 👋"]);
+    }
+
+    #[test]
+    fn strips_leading_bom_only() {
+        let bom = "\u{feff}".as_bytes();
+        let events = decode(&[&bom[..1], &bom[1..], b"data: first\n\n\xef\xbb\xbfdata: second\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "first");
+    }
+
+    #[test]
+    fn ignores_comments() {
+        let events = decode(&[b": keep-alive\n\n:ping\ndata: value\n: between\n\n"]);
+        assert_eq!(data(&events), ["value"]);
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode(&[b"data: one\ndata:two\ndata\ndata:  three\n\n"]);
+        // Only a single leading space is stripped, a bare `data` adds an empty line
+        assert_eq!(data(&events), ["one\ntwo\n\n three"]);
+    }
+
+    #[test]
+    fn event_without_data_is_not_dispatched() {
+        let events = decode(&[b"event: ping\n\ndata: after\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+    }
+
+    #[test]
+    fn keeps_id_and_retry() {
+        let events = decode(&[b"id: 1\nretry: 3000\ndata: a\n\nretry: soon\ndata: b\n\nid: bad\0id\nid: 2\ndata: c\n\n"]);
+        let fields: Vec<_> = events.iter().map(|event| (event.id.as_deref(), event.retry)).collect();
+        // The last ID carries over, an invalid retry or an ID with NULL is ignored
+        assert_eq!(fields, [(Some("1"), Some(3000)), (Some("1"), Some(3000)), (Some("2"), Some(3000))]);
+    }
+
+    #[test]
+    fn finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: {\"done\": true}").is_empty());
+        let event = decoder.finish().expect("trailing event");
+        assert_eq!(event.data, r#"{"done": true}"#);
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn finish_dispatches_event_missing_blank_line() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: last\n").is_empty());
+        assert_eq!(decoder.finish().map(|event| event.data), Some("last".to_string()));
+    }
+
+    #[tokio::test]
+    async fn for_each_event_stops_at_error_event() {
+        let chunks: Vec<Result<&[u8], PluelyError>> = vec![
+            Ok(b"data: one\n\n"),
+            Ok(b"event: error\ndata: {\"error\": {\"type\": \"overloaded_error\", \"message\": \"Overloaded\"}}\n\n"),
+            Ok(b"data: never\n\n"),
+        ];
+        let mut seen = Vec::new();
+        let result = for_each_event(futures_util::stream::iter(chunks), |event| {
+            seen.push(event.data);
+            Ok(ControlFlow::Continue(()))
+        })
+        .await;
+
+        assert_eq!(seen, ["one"]);
+        assert!(matches!(result, Err(PluelyError::Server { status: 529, .. })));
+    }
+}
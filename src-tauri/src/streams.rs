@@ -0,0 +1,192 @@
+// Pluely chat stream registry, tags stream events with a request ID so they can be cancelled
+use futures_util::future::{abortable, AbortHandle};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use crate::error::{PluelyError, PluelyResult};
+use crate::providers::StreamDelta;
+use crate::retry::{self, Failure, RetryPolicy};
+use crate::sse;
+
+// Running chat streams by request ID
+#[derive(Default)]
+pub struct ChatStreams(Mutex<HashMap<String, AbortHandle>>);
+
+impl ChatStreams {
+    // Single inserts and removes can't leave the map half updated, so a poisoned lock is
+    // still usable, and panicking here would be a panic in Drop
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, AbortHandle>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatStreamChunk {
+    request_id: String,
+    chunk: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatStreamComplete {
+    request_id: String,
+    response: String,
+    error: Option<PluelyError>,
+    cancelled: bool,
+}
+
+// Emits chunks for a single request
+#[derive(Clone)]
+pub struct ChatStreamSink {
+    app: AppHandle,
+    request_id: String,
+}
+
+impl ChatStreamSink {
+    pub fn emit_chunk(&self, chunk: &str) {
+        let _ = self.app.emit(
+            "chat_stream_chunk",
+            ChatStreamChunk {
+                request_id: self.request_id.clone(),
+                chunk: chunk.to_string(),
+            },
+        );
+    }
+}
+
+// Emits `chat_stream_complete` when dropped, so a stream aborted mid-way still completes exactly once
+struct Completion {
+    app: AppHandle,
+    request_id: String,
+    // None when the task was aborted before the stream finished
+    result: Option<PluelyResult<String>>,
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        if let Some(streams) = self.app.try_state::<ChatStreams>() {
+            streams.lock().remove(&self.request_id);
+        }
+
+        let (response, error, cancelled) = match self.result.take() {
+            Some(Ok(response)) => (response, None, false),
+            Some(Err(e)) => (String::new(), Some(e), false),
+            None => (String::new(), None, true),
+        };
+        let _ = self.app.emit(
+            "chat_stream_complete",
+            ChatStreamComplete {
+                request_id: std::mem::take(&mut self.request_id),
+                response,
+                error,
+                cancelled,
+            },
+        );
+    }
+}
+
+/// Runs a chat stream in the background and returns its request ID.
+/// `chat_stream_complete` is emitted once the stream finishes, fails or is cancelled.
+pub fn spawn_chat_stream<F, Fut>(app: &AppHandle, run: F) -> String
+where
+    F: FnOnce(ChatStreamSink) -> Fut,
+    Fut: Future<Output = PluelyResult<String>> + Send + 'static,
+{
+    let request_id = Uuid::new_v4().to_string();
+    let stream = run(ChatStreamSink {
+        app: app.clone(),
+        request_id: request_id.clone(),
+    });
+
+    // Created out here so it is dropped with the task even if it is aborted before its first poll
+    let completion = Completion {
+        app: app.clone(),
+        request_id: request_id.clone(),
+        result: None,
+    };
+
+    // The abort handle exists before the task, so it is registered before the task can finish
+    // and a cancel arriving before the first poll still takes effect
+    let (task, abort) = abortable(async move {
+        // Moves the whole guard into the task rather than just its `result` field
+        let mut completion = completion;
+        // No await after this, so an abort arriving now can't turn a finished stream into a cancelled one
+        completion.result = Some(stream.await);
+    });
+    app.state::<ChatStreams>().lock().insert(request_id.clone(), abort);
+
+    // Not under the lock: a runtime shutting down drops the task right here, and its guard locks the map
+    tokio::spawn(task);
+
+    request_id
+}
+
+/// Sends a streaming request, retrying per `policy` until the first chunk, and forwards the
+/// content `parse` finds in each server-sent event to `sink`. Returns the whole response.
+pub async fn stream_response<P>(
+    request: reqwest::RequestBuilder,
+    policy: RetryPolicy,
+    sink: ChatStreamSink,
+    name: &str,
+    parse: P,
+) -> PluelyResult<String>
+where
+    P: Fn(&str) -> PluelyResult<StreamDelta> + Sync,
+{
+    retry::with_retry(&policy, || {
+        let request = retry::try_clone(&request);
+        let sink = sink.clone();
+        let (policy, parse) = (&policy, &parse);
+        async move {
+            let response = retry::send_once(request?, policy)
+                .await
+                .map_err(|f| f.context(&format!("Failed to make {} request", name)))?;
+
+            // Handle streaming response
+            let mut full_response = String::new();
+            let result = sse::for_each_event(response.bytes_stream(), |event| {
+                if event.data.is_empty() {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                match parse(&event.data)? {
+                    StreamDelta::Content(content) => {
+                        full_response.push_str(&content);
+                        sink.emit_chunk(&content);
+                        Ok(ControlFlow::Continue(()))
+                    }
+                    StreamDelta::Done => Ok(ControlFlow::Break(())),
+                    StreamDelta::Skip => Ok(ControlFlow::Continue(())),
+                }
+            })
+            .await;
+
+            match result {
+                Ok(()) => Ok(full_response),
+                // Never retry once part of the answer reached the user
+                Err(e) if !full_response.is_empty() => Err(Failure::fatal(e)),
+                Err(e) => Err(e.into()),
+            }
+        }
+    })
+    .await
+}
+
+// Aborts a running chat stream, returns false if it already finished.
+// The task itself emits the cancelled `chat_stream_complete` as it is dropped.
+#[tauri::command]
+pub fn cancel_chat_stream(app: AppHandle, request_id: String) -> bool {
+    let handle = app.state::<ChatStreams>().lock().remove(&request_id);
+
+    match handle {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,162 @@
+#!/usr/bin/env python3
+"""Renders the VAD fixtures: 16 kHz mono 16-bit WAVs, deterministic for a given seed.
+
+    python3 render.py    # writes the WAVs next to this script
+"""
+import math
+import os
+import random
+import struct
+import wave
+
+RATE = 16000
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def write(name, samples):
+    peak = max(1e-9, max(abs(s) for s in samples))
+    if peak > 0.99:
+        samples = [s * 0.99 / peak for s in samples]
+    with wave.open(os.path.join(HERE, name), "wb") as out:
+        out.setnchannels(1)
+        out.setsampwidth(2)
+        out.setframerate(RATE)
+        out.writeframes(b"".join(struct.pack("<h", int(round(s * 32767))) for s in samples))
+
+
+class Resonator:
+    """Two-pole resonator, the building block of a formant synthesizer."""
+
+    def __init__(self, hz, bandwidth):
+        r = math.exp(-math.pi * bandwidth / RATE)
+        self.a1 = 2 * r * math.cos(2 * math.pi * hz / RATE)
+        self.a2 = -r * r
+        self.gain = 1 - self.a1 - self.a2
+        self.y1 = self.y2 = 0.0
+
+    def tune(self, hz, bandwidth):
+        r = math.exp(-math.pi * bandwidth / RATE)
+        self.a1 = 2 * r * math.cos(2 * math.pi * hz / RATE)
+        self.a2 = -r * r
+        self.gain = 1 - self.a1 - self.a2
+
+    def __call__(self, x):
+        y = self.gain * x + self.a1 * self.y1 + self.a2 * self.y2
+        self.y2, self.y1 = self.y1, y
+        return y
+
+
+# F1, F2, F3 of a male voice
+VOWELS = {
+    "a": (730, 1090, 2440),
+    "e": (530, 1840, 2480),
+    "i": (270, 2290, 3010),
+    "o": (570, 840, 2410),
+    "u": (300, 870, 2240),
+}
+BANDWIDTHS = (90, 110, 170)
+
+
+def speech(rng):
+    """A short phrase: voiced syllables with fricative onsets, pitch falling over the phrase."""
+    words = [["ha", "lo"], ["thi", "sis"], ["a"], ["te", "st"], ["fo", "ra"], ["vo", "ice"], ["de", "tec", "tor"]]
+    out = [0.0] * int(0.25 * RATE)
+    formants = [Resonator(hz, bw) for hz, bw in zip(VOWELS["a"], BANDWIDTHS)]
+    fricative = Resonator(4500, 1500)
+    phase = 0.0
+    total = sum(len(w) for w in words)
+    syllable = 0
+
+    for word in words:
+        for part in word:
+            consonant = part[0] not in VOWELS
+            vowel = next((c for c in part if c in VOWELS), "a")
+            if consonant:
+                length = int(rng.uniform(0.04, 0.08) * RATE)
+                for i in range(length):
+                    env = math.sin(math.pi * i / length)
+                    out.append(0.04 * env * fricative(rng.gauss(0, 1)))
+
+            target = VOWELS[vowel]
+            for res, hz, bw in zip(formants, target, BANDWIDTHS):
+                res.tune(hz * rng.uniform(0.95, 1.05), bw)
+            length = int(rng.uniform(0.14, 0.24) * RATE)
+            f0_start = 150 - 40 * syllable / total
+            for i in range(length):
+                t = i / length
+                f0 = f0_start * (1.05 - 0.1 * t) * (1 + 0.01 * rng.gauss(0, 1))
+                phase += f0 / RATE
+                if phase >= 1:
+                    phase -= 1
+                # Rosenberg glottal pulse, open for 60% of the period
+                glottal = 0.5 * (1 - math.cos(math.pi * phase / 0.6)) if phase < 0.6 else 0.0
+                x = glottal + 0.02 * rng.gauss(0, 1)
+                for res in formants:
+                    x = res(x)
+                env = min(1.0, t / 0.15, (1 - t) / 0.25)
+                out.append(0.5 * env * x)
+            syllable += 1
+        out.extend([0.0] * int(rng.uniform(0.05, 0.3) * RATE))
+
+    # Radiation at the lips and a little room noise
+    radiated = [out[0]] + [out[i] - 0.95 * out[i - 1] for i in range(1, len(out))]
+    return [3.0 * s + 0.0005 * rng.gauss(0, 1) for s in radiated]
+
+
+def music(rng):
+    """A pad playing a chord progression over a bass line, like background music."""
+    chords = [(261.6, 329.6, 392.0), (220.0, 261.6, 329.6), (174.6, 220.0, 261.6), (196.0, 246.9, 293.7)]
+    bars = 4
+    bar = int(0.75 * RATE)
+    out = []
+    for n in range(bars):
+        notes = chords[n % len(chords)]
+        bass = notes[0] / 2
+        for i in range(bar):
+            t = (n * bar + i) / RATE
+            x = 0.0
+            for hz in notes:
+                for k, gain in ((1, 1.0), (2, 0.4), (3, 0.2), (4, 0.1)):
+                    x += gain * math.sin(2 * math.pi * hz * k * t)
+            x += 1.2 * math.sin(2 * math.pi * bass * t) + 0.3 * math.sin(4 * math.pi * bass * t)
+            # Crossfade between chords so the level stays steady
+            edge = min(1.0, i / 400, (bar - i) / 400)
+            out.append(0.03 * (0.8 + 0.2 * edge) * x)
+    return [s + 0.0005 * rng.gauss(0, 1) for s in out]
+
+
+def keyboard(rng):
+    """Typing: short broadband clicks with a plastic resonance, at a typing rhythm."""
+    out = [0.0005 * rng.gauss(0, 1) for _ in range(int(3.0 * RATE))]
+    body = Resonator(2800, 900)
+    position = int(0.1 * RATE)
+    while position < len(out) - RATE // 20:
+        length = int(rng.uniform(0.008, 0.02) * RATE)
+        level = rng.uniform(0.15, 0.4)
+        for i in range(length):
+            out[position + i] += level * math.exp(-i / (0.003 * RATE)) * body(rng.gauss(0, 1))
+        # Key release a little later
+        release = position + int(rng.uniform(0.05, 0.09) * RATE)
+        for i in range(length // 2):
+            out[release + i] += 0.5 * level * math.exp(-i / (0.002 * RATE)) * body(rng.gauss(0, 1))
+        position += int(rng.uniform(0.12, 0.3) * RATE)
+    return out
+
+
+def notification(rng):
+    """A two-note chime with inharmonic bell partials, decaying."""
+    out = [0.0] * int(1.6 * RATE)
+    for start, hz in ((0.2, 1318.5), (0.45, 1046.5)):
+        offset = int(start * RATE)
+        for i in range(len(out) - offset):
+            t = i / RATE
+            x = sum(gain * math.sin(2 * math.pi * hz * ratio * t) * math.exp(-t * decay)
+                    for ratio, gain, decay in ((1.0, 1.0, 4.0), (2.76, 0.3, 7.0), (5.4, 0.1, 12.0)))
+            attack = min(1.0, i / 40)
+            out[offset + i] += 0.25 * attack * x
+    return [s + 0.0005 * rng.gauss(0, 1) for s in out]
+
+
+if __name__ == "__main__":
+    for name, render in (("speech", speech), ("music", music), ("keyboard", keyboard), ("notification", notification)):
+        write(name + ".wav", render(random.Random(name)))
@@ -10,6 +10,7 @@ import {
 import { invoke } from "@tauri-apps/api/core";
 import { openUrl } from "@tauri-apps/plugin-opener";
 import { useApp } from "@/contexts";
+import { getErrorMessage } from "@/lib";
 import {
   Command,
   CommandEmpty,
@@ -33,6 +34,11 @@ interface ActivationResponse {
   };
 }
 
+interface DeactivationResponse {
+  deactivated: boolean;
+  error?: string;
+}
+
 interface CheckoutResponse {
   success?: boolean;
   checkout_url?: string;
@@ -40,7 +46,7 @@ interface CheckoutResponse {
 }
 
 interface StorageResult {
-  license_key?: string;
+  masked_license_key?: string;
   instance_id?: string;
   selected_pluely_model?: string;
 }
@@ -63,12 +69,13 @@ export const PluelyApiSetup = () => {
   const { pluelyApiEnabled, setPluelyApiEnabled } = useApp();
 
   const [licenseKey, setLicenseKey] = useState("");
-  const [storedLicenseKey, setStoredLicenseKey] = useState<string | null>(null);
   const [maskedLicenseKey, setMaskedLicenseKey] = useState<string | null>(null);
   const [isLoading, setIsLoading] = useState(false);
   const [isCheckoutLoading, setIsCheckoutLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [success, setSuccess] = useState<string | null>(null);
+  // Deactivation failed, e.g. offline or the instance is already gone on the server
+  const [canForgetLicense, setCanForgetLicense] = useState(false);
   const [models, setModels] = useState<Model[]>([]);
   const [isModelsLoading, setIsModelsLoading] = useState(false);
   const [selectedModel, setSelectedModel] = useState<Model | null>(null);
@@ -110,18 +117,8 @@ export const PluelyApiSetup = () => {
       // Get all stored data in one call
       const storage = await invoke<StorageResult>("secure_storage_get");
 
-      if (storage.license_key) {
-        setStoredLicenseKey(storage.license_key);
-
-        // Get masked version from Tauri command
-        const masked = await invoke<string>("mask_license_key_cmd", {
-          licenseKey: storage.license_key,
-        });
-        setMaskedLicenseKey(masked);
-      } else {
-        setStoredLicenseKey(null);
-        setMaskedLicenseKey(null);
-      }
+      // The key itself never leaves the backend
+      setMaskedLicenseKey(storage.masked_license_key ?? null);
 
       if (storage.selected_pluely_model) {
         try {
@@ -137,7 +134,6 @@ export const PluelyApiSetup = () => {
     } catch (err) {
       console.error("Failed to load license status:", err);
       // If we can't read from storage, assume no license is stored
-      setStoredLicenseKey(null);
       setMaskedLicenseKey(null);
       setSelectedModel(null);
     }
@@ -162,20 +158,7 @@ export const PluelyApiSetup = () => {
       );
 
       if (response.activated && response.instance) {
-        // Store the license data securely in one call
-        await invoke("secure_storage_save", {
-          items: [
-            {
-              key: LICENSE_KEY_STORAGE_KEY,
-              value: licenseKey.trim(),
-            },
-            {
-              key: INSTANCE_ID_STORAGE_KEY,
-              value: response.instance.id,
-            },
-          ],
-        });
-
+        // The backend already stored the license key and instance
         setSuccess("License activated successfully!");
         setLicenseKey(""); // Clear the input
 
@@ -188,36 +171,73 @@ export const PluelyApiSetup = () => {
       }
     } catch (err) {
       console.error("License activation failed:", err);
-      setError(typeof err === "string" ? err : "Failed to activate license");
+      setError(getErrorMessage(err) || "Failed to activate license");
     } finally {
       setIsLoading(false);
     }
   };
 
+  // The offer only stands next to the error it came with
+  useEffect(() => {
+    if (!error) setCanForgetLicense(false);
+  }, [error]);
+
+  // Removes all license data from secure storage in one call
+  const removeStoredLicense = async () => {
+    await invoke("secure_storage_remove", {
+      keys: [
+        LICENSE_KEY_STORAGE_KEY,
+        INSTANCE_ID_STORAGE_KEY,
+        SELECTED_PLUELY_MODEL_STORAGE_KEY,
+      ],
+    });
+
+    // Disable Pluely API when license is removed
+    setPluelyApiEnabled(false);
+
+    await loadLicenseStatus(); // Reload status
+  };
+
   const handleRemoveLicense = async () => {
     setIsLoading(true);
     setError(null);
     setSuccess(null);
 
     try {
-      // Remove all license data from secure storage in one call
-      await invoke("secure_storage_remove", {
-        keys: [
-          LICENSE_KEY_STORAGE_KEY,
-          INSTANCE_ID_STORAGE_KEY,
-          SELECTED_PLUELY_MODEL_STORAGE_KEY,
-        ],
-      });
+      // Free this machine's activation slot, the backend also forgets the license once it is freed
+      const response: DeactivationResponse = await invoke("deactivate_license");
+      if (!response.deactivated) {
+        // Still activated on the server, removing it here is left to the user
+        setError(
+          `License could not be deactivated: ${response.error || "Unknown error"}`
+        );
+        setCanForgetLicense(true);
+        return;
+      }
 
-      setSuccess("License removed successfully!");
+      await removeStoredLicense();
+      setSuccess("License deactivated successfully!");
+    } catch (err) {
+      console.error("Failed to deactivate license:", err);
+      setError(getErrorMessage(err) || "Failed to deactivate license");
+      setCanForgetLicense(true);
+    } finally {
+      setIsLoading(false);
+    }
+  };
 
-      // Disable Pluely API when license is removed
-      setPluelyApiEnabled(false);
+  // Confirmed from the error message after deactivation failed
+  const handleForgetLicense = async () => {
+    setIsLoading(true);
+    setError(null);
+    setSuccess(null);
 
-      await loadLicenseStatus(); // Reload status
+    try {
+      await removeStoredLicense();
+      setSuccess("License removed from this device.");
     } catch (err) {
       console.error("Failed to remove license:", err);
-      setError("Failed to remove license");
+      setError(getErrorMessage(err) || "Failed to remove license");
     } finally {
       setIsLoading(false);
     }
@@ -250,7 +270,7 @@ export const PluelyApiSetup = () => {
   };
 
   const handleKeyDown = (e: React.KeyboardEvent<HTMLInputElement>) => {
-    if (e.key === "Enter" && !storedLicenseKey) {
+    if (e.key === "Enter" && !maskedLicenseKey) {
       handleActivateLicense();
     }
   };
@@ -270,7 +290,7 @@ export const PluelyApiSetup = () => {
       }
     } catch (err) {
       console.error("Failed to get checkout URL:", err);
-      setError(typeof err === "string" ? err : "Failed to get checkout URL");
+      setError(getErrorMessage(err) || "Failed to get checkout URL");
     } finally {
       setIsCheckoutLoading(false);
     }
@@ -347,7 +367,7 @@ export const PluelyApiSetup = () => {
             description="Pluely license to unlock faster responses, quicker support and premium features."
           />
           <div className="flex flex-row items-center gap-2">
-            {!storedLicenseKey && (
+            {!maskedLicenseKey && (
               <Button
                 onClick={handleGetLicenseKey}
                 disabled={isCheckoutLoading}
@@ -363,6 +383,23 @@ export const PluelyApiSetup = () => {
         {error && (
           <div className="p-3 rounded-lg border border-red-200 bg-red-50 dark:border-red-800 dark:bg-red-950">
             <p className="text-sm text-red-700 dark:text-red-400">{error}</p>
+            {canForgetLicense && (
+              <div className="mt-2 space-y-2">
+                <p className="text-xs text-red-700 dark:text-red-400">
+                  You can still remove the license from this device. If it
+                  is activated on the server, this machine keeps using an
+                  activation slot until it is deactivated from another device.
+                </p>
+                <Button
+                  onClick={handleForgetLicense}
+                  disabled={isLoading}
+                  size="sm"
+                  variant="destructive"
+                >
+                  Remove from this device anyway
+                </Button>
+              </div>
+            )}
           </div>
         )}
 
@@ -448,7 +485,7 @@ export const PluelyApiSetup = () => {
         </Popover>
         {/* License Key Input or Display */}
         <div className="space-y-2">
-          {!storedLicenseKey ? (
+          {!maskedLicenseKey ? (
             <>
               <div className="space-y-1">
                 <label className="text-sm font-medium">License Key</label>
@@ -513,7 +550,7 @@ export const PluelyApiSetup = () => {
                   )}
                 </Button>
               </div>
-              {storedLicenseKey ? (
+              {maskedLicenseKey ? (
                 <div className="-mt-1">
                   <p className="text-sm font-medium text-muted-foreground select-auto">
                     If you need any help or any assistance, contact
@@ -529,7 +566,7 @@ export const PluelyApiSetup = () => {
         <Header
           title={`${pluelyApiEnabled ? "Disable" : "Enable"} Pluely API`}
           description={
-            storedLicenseKey
+            maskedLicenseKey
               ? pluelyApiEnabled
                 ? "Using all pluely APIs for audio, and chat."
                 : "Using all your own AI Providers for audio, and chat."
@@ -539,7 +576,7 @@ export const PluelyApiSetup = () => {
         <Switch
           checked={pluelyApiEnabled}
           onCheckedChange={setPluelyApiEnabled}
-          disabled={!storedLicenseKey} // Disable if no license is stored
+          disabled={!maskedLicenseKey} // Disable if no license is stored
         />
       </div>
     </div>
@@ -3,7 +3,7 @@ import { useWindowResize, useGlobalShortcuts } from ".";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { useApp } from "@/contexts";
-import { fetchSTT, fetchAIResponse } from "@/lib/functions";
+import { fetchSTT, fetchAIResponse, getErrorMessage } from "@/lib/functions";
 import {
   DEFAULT_QUICK_ACTIONS,
   DEFAULT_SYSTEM_PROMPT,
@@ -34,6 +34,22 @@ export interface ChatConversation {
   updatedAt: number;
 }
 
+// Payload of the backend `speech-detected` event
+interface SpeechDetectedPayload {
+  source: "speaker" | "microphone";
+  channel: "local" | "remote";
+  segment_id: number;
+  start_ms: number;
+  end_ms: number;
+  audio: string;
+}
+
+interface CaptureStoppedPayload {
+  source: "speaker" | "microphone";
+  channel: "local" | "remote";
+  reason: string;
+}
+
 export type useSystemAudioType = ReturnType<typeof useSystemAudio>;
 
 export function useSystemAudio() {
@@ -107,17 +123,37 @@ export function useSystemAudio() {
     }
   }, []);
 
+  // The capture died after starting, e.g. the audio server went away for good
+  useEffect(() => {
+    if (!capturing) return;
+    let stoppedUnlisten: (() => void) | undefined;
+
+    listen<CaptureStoppedPayload>("capture-stopped", (event) => {
+      if (event.payload.source !== "speaker") return;
+      setCapturing(false);
+      setError(`System audio capture stopped: ${event.payload.reason}`);
+    }).then((unlisten) => {
+      stoppedUnlisten = unlisten;
+    });
+
+    return () => {
+      if (stoppedUnlisten) stoppedUnlisten();
+    };
+  }, [capturing]);
+
   // Handle single speech detection event
   useEffect(() => {
     let speechUnlisten: (() => void) | undefined;
 
     const setupEventListener = async () => {
       try {
-        speechUnlisten = await listen("speech-detected", async (event) => {
+        speechUnlisten = await listen<SpeechDetectedPayload>("speech-detected", async (event) => {
           try {
             if (!capturing) return;
+            // Microphone segments arrive on the same event
+            if (event.payload.source !== "speaker") return;
 
-            const base64Audio = event.payload as string;
+            const base64Audio = event.payload.audio;
             // Convert to blob
             const binaryString = atob(base64Audio);
             const bytes = new Uint8Array(binaryString.length);
@@ -384,8 +420,7 @@ export function useSystemAudio() {
         updatedAt: 0,
       });
     } catch (err) {
-      const errorMessage = err instanceof Error ? err.message : String(err);
-      setError(errorMessage);
+      setError(getErrorMessage(err));
     }
   }, []);
 
@@ -3,9 +3,10 @@ import {
   deepVariableReplacer,
   extractVariables,
   getByPath,
+  getErrorMessage,
   getStreamingContent,
 } from "./common.function";
-import { Message, TYPE_PROVIDER } from "@/types";
+import { Message, PluelyError, TYPE_PROVIDER } from "@/types";
 import { fetch as tauriFetch } from "@tauri-apps/plugin-http";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
@@ -43,22 +44,32 @@ async function* fetchPluelyAIResponse(params: {
       imageBase64 = imagesBase64.length === 1 ? imagesBase64[0] : imagesBase64;
     }
 
-    // Set up streaming event listener
-    let streamComplete = false;
-    const streamChunks: string[] = [];
+    // Set up streaming event listeners, events are tagged with the request ID
+    // returned by chat_stream, so buffer until we know which request is ours
+    const streamChunks: { request_id: string; chunk: string }[] = [];
+    const completed = new Map<
+      string,
+      { error?: PluelyError | null; cancelled: boolean }
+    >();
 
-    const unlisten = await listen("chat_stream_chunk", (event) => {
-      const chunk = event.payload as string;
-      streamChunks.push(chunk);
-    });
+    const unlisten = await listen<{ request_id: string; chunk: string }>(
+      "chat_stream_chunk",
+      (event) => {
+        streamChunks.push(event.payload);
+      }
+    );
 
-    const unlistenComplete = await listen("chat_stream_complete", () => {
-      streamComplete = true;
+    const unlistenComplete = await listen<{
+      request_id: string;
+      error?: PluelyError | null;
+      cancelled: boolean;
+    }>("chat_stream_complete", (event) => {
+      completed.set(event.payload.request_id, event.payload);
     });
 
     try {
       // Start the streaming request
-      await invoke("chat_stream", {
+      const requestId = await invoke<string>("chat_stream", {
         userMessage,
         systemPrompt,
         imageBase64,
@@ -67,28 +78,36 @@ async function* fetchPluelyAIResponse(params: {
 
       // Yield chunks as they come in
       let lastIndex = 0;
-      while (!streamComplete) {
+      while (!completed.has(requestId)) {
         // Wait a bit for chunks to accumulate
         await new Promise((resolve) => setTimeout(resolve, 50));
 
         // Yield any new chunks
         for (let i = lastIndex; i < streamChunks.length; i++) {
-          yield streamChunks[i];
+          if (streamChunks[i].request_id === requestId) {
+            yield streamChunks[i].chunk;
+          }
         }
         lastIndex = streamChunks.length;
       }
 
       // Yield any remaining chunks
       for (let i = lastIndex; i < streamChunks.length; i++) {
-        yield streamChunks[i];
+        if (streamChunks[i].request_id === requestId) {
+          yield streamChunks[i].chunk;
+        }
+      }
+
+      const result = completed.get(requestId);
+      if (result?.error) {
+        throw result.error;
       }
     } finally {
       unlisten();
       unlistenComplete();
     }
   } catch (error) {
-    const errorMessage = error instanceof Error ? error.message : String(error);
-    yield `Pluely API Error: ${errorMessage}`;
+    yield `Pluely API Error: ${getErrorMessage(error)}`;
   }
 }
 
@@ -1,4 +1,4 @@
-import { Message } from "@/types";
+import { Message, PluelyError } from "@/types";
 
 export function getByPath(obj: any, path: string): any {
   if (!path) return obj;
@@ -20,6 +20,26 @@ export function setByPath(obj: any, path: string, value: any): void {
   current[keys[keys.length - 1].replace(/\[(\d+)\]/g, ".$1")] = value;
 }
 
+export function isPluelyError(error: unknown): error is PluelyError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "kind" in error &&
+    "message" in error
+  );
+}
+
+// Readable message for errors thrown by `invoke`, which may be a PluelyError object
+export function getErrorMessage(error: unknown): string {
+  if (isPluelyError(error)) {
+    if (error.kind === "server" && error.status) {
+      return `Server error (${error.status}): ${error.message}`;
+    }
+    return error.message;
+  }
+  return error instanceof Error ? error.message : String(error);
+}
+
 export async function blobToBase64(blob: Blob): Promise<string> {
   return new Promise((resolve, reject) => {
     const reader = new FileReader();
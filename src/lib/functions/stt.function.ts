@@ -2,6 +2,7 @@ import {
   deepVariableReplacer,
   getByPath,
   blobToBase64,
+  getErrorMessage,
 } from "./common.function";
 import { fetch as tauriFetch } from "@tauri-apps/plugin-http";
 import { invoke } from "@tauri-apps/api/core";
@@ -31,8 +32,7 @@ async function fetchPluelySTT(audio: File | Blob): Promise<string> {
       return response.error || "Transcription failed";
     }
   } catch (error) {
-    const errorMessage = error instanceof Error ? error.message : String(error);
-    return `Pluely STT Error: ${errorMessage}`;
+    return `Pluely STT Error: ${getErrorMessage(error)}`;
   }
 }
 
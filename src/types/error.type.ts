@@ -0,0 +1,19 @@
+// Mirrors `PluelyError` in src-tauri/src/error.rs
+export type PluelyErrorKind =
+  | "network"
+  | "timeout"
+  | "auth"
+  | "rate_limited"
+  | "server"
+  | "parse"
+  | "storage"
+  | "config"
+  | "unsupported"
+  | "audio";
+
+export interface PluelyError {
+  kind: PluelyErrorKind;
+  message: string;
+  status?: number;
+  retry_after?: number | null;
+}
@@ -4,3 +4,4 @@ export * from "./context.type";
 export * from "./provider.type";
 export * from "./settings.hook";
 export * from "./completion";
+export * from "./error.type";